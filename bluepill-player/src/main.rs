@@ -5,7 +5,7 @@ extern crate panic_semihosting;
 
 use cortex_m::peripheral::syst::SystClkSource;
 use cortex_m_rt::{entry, exception};
-use softsynth::{Adsr, Oscillator, Sound, MAX_VOL, RATE};
+use softsynth::{Adsr, Oscillator, Sound, MAX_VOL};
 use stm32f1xx_hal::prelude::*;
 use stm32f1xx_hal::{gpio, pwm, stm32};
 
@@ -84,7 +84,8 @@ fn main() -> ! {
     };
 
     let mut oscillator = Adsr::new(Oscillator::default(), 100, 1000, MAX_VOL / 3 * 2, 2000);
-    oscillator.set_freq(440);
+    oscillator.set_freq_hz(440);
+    let rate = oscillator.rate();
 
     let mut gpiob = device.GPIOB.split(&mut rcc.apb2);
     let button0 = gpiob.pb12.into_pull_up_input(&mut gpiob.crh);
@@ -109,7 +110,7 @@ fn main() -> ! {
     }
 
     core.SYST.set_clock_source(SystClkSource::Core);
-    core.SYST.set_reload(72_000_000 / RATE);
+    core.SYST.set_reload(72_000_000 / rate);
     core.SYST.enable_interrupt();
     core.SYST.enable_counter();
 
@@ -124,7 +125,7 @@ struct Unbouncer {
     nb_changes: u32,
 }
 impl Unbouncer {
-    fn is_change(&mut self, new: u16) -> bool {
+    fn is_change(&mut self, new: u16, rate: u32) -> bool {
         if new == self.cur {
             self.nb_changes = 0;
             false
@@ -132,7 +133,7 @@ impl Unbouncer {
             self.new = new;
             self.nb_changes = 0;
             false
-        } else if self.nb_changes > RATE * 50 / 1000 {
+        } else if self.nb_changes > rate * 50 / 1000 {
             self.cur = new;
             self.nb_changes = 0;
             true
@@ -172,11 +173,11 @@ fn SysTick() {
         _ => unreachable!(),
     } as u16;
 
-    if UNBOUNCER.is_change(freq) {
+    if UNBOUNCER.is_change(freq, context.oscillator.rate()) {
         if freq == 0 {
             context.oscillator.stop();
         } else {
-            context.oscillator.set_freq(freq);
+            context.oscillator.set_freq_hz(freq);
         }
     }
     context.oscillator.advance();
@@ -0,0 +1,47 @@
+use byteorder::{WriteBytesExt, LE};
+use softsynth::midi::Smf;
+use softsynth::{Adsr, Oscillator, SongPlayer, Sound, MAX_VOL, RATE};
+use std::io::Write;
+
+fn main() -> std::io::Result<()> {
+    let path = match std::env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: midi-to-wav FILE.mid > FILE.wav");
+            std::process::exit(1);
+        }
+    };
+    let data = std::fs::read(path)?;
+    let smf = Smf::parse(&data)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{:?}", e)))?;
+
+    let mut oscillator = Adsr::new(Oscillator::default(), 10, 300, MAX_VOL / 3 * 2, 10);
+    oscillator.set_vol(MAX_VOL / 4);
+    let player: SongPlayer<_, _, 16> =
+        SongPlayer::new(smf.tracks().map(|track| (oscillator.clone(), track)));
+    let len = player.len();
+
+    let stdout = std::io::stdout();
+    let mut stdout = stdout.lock();
+
+    stdout.write_all(b"RIFF")?;
+    stdout.write_u32::<LE>(16 + 8 + 8 + 4 + len as u32 * 2)?;
+    stdout.write_all(b"WAVE")?;
+
+    stdout.write_all(b"fmt ")?;
+    stdout.write_u32::<LE>(16)?;
+    stdout.write_u16::<LE>(1)?; // PCM
+    stdout.write_u16::<LE>(1)?; // mono
+    stdout.write_u32::<LE>(RATE)?; // freq
+    stdout.write_u32::<LE>(RATE * 16 / 8)?; // bytes/s
+    stdout.write_u16::<LE>(16 / 8)?; // bytes/block
+    stdout.write_u16::<LE>(16)?; // bits/sample
+
+    stdout.write_all(b"data")?;
+    stdout.write_u32::<LE>(len as u32 * 2)?;
+    for s in player {
+        stdout.write_i16::<LE>(s)?;
+    }
+
+    Ok(())
+}
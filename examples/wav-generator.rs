@@ -1,29 +1,84 @@
 use byteorder::{WriteBytesExt, LE};
-use softsynth::{mix, Adsr, Oscillator, Sound, MAX_VOL, RATE};
+use softsynth::pitch::*;
+use softsynth::songs::{Mark, Mark::*, Song, Track};
+use softsynth::{Adsr, Oscillator, SongPlayer, Sound, MAX_VOL, RATE};
 use std::io::Write;
 
+// Frère Jacques as a canon: each voice enters 2 bars after the
+// previous one, and plays the theme twice.
+macro_rules! voice {
+    ($($rest:expr)?) => {
+        [
+            $(Note(C5, $rest, 1, 0),)?
+            RepeatStart,
+            Note(C5, 1, 4, 90),
+            Note(D5, 1, 4, 90),
+            Note(E5, 1, 4, 90),
+            Note(C5, 1, 4, 90),
+            Note(C5, 1, 4, 90),
+            Note(D5, 1, 4, 90),
+            Note(E5, 1, 4, 90),
+            Note(C5, 1, 4, 90),
+            Note(E5, 1, 4, 90),
+            Note(F5, 1, 4, 90),
+            Note(G5, 1, 2, 90),
+            Note(E5, 1, 4, 90),
+            Note(F5, 1, 4, 90),
+            Note(G5, 1, 2, 90),
+            Note(G5, 3, 16, 90),
+            Note(A5, 1, 16, 90),
+            Note(G5, 1, 8, 90),
+            Note(F5, 1, 8, 90),
+            Note(E5, 1, 4, 90),
+            Note(C5, 1, 4, 90),
+            Note(G5, 3, 16, 90),
+            Note(A5, 1, 16, 90),
+            Note(G5, 1, 8, 90),
+            Note(F5, 1, 8, 90),
+            Note(E5, 1, 4, 90),
+            Note(C5, 1, 4, 90),
+            Note(C5, 1, 4, 90),
+            Note(G4, 1, 4, 90),
+            Note(C5, 1, 2, 90),
+            Note(C5, 1, 4, 90),
+            Note(G4, 1, 4, 90),
+            Note(C5, 1, 2, 90),
+            RepeatEnd(2),
+        ]
+    };
+}
+static VOICE1: [Mark; 34] = voice!();
+static VOICE2: [Mark; 35] = voice!(2);
+static VOICE3: [Mark; 35] = voice!(4);
+static VOICE4: [Mark; 35] = voice!(6);
+static CANON_TRACKS: [Track; 4] = [
+    Track {
+        instrument: 0,
+        notes: &VOICE1,
+    },
+    Track {
+        instrument: 0,
+        notes: &VOICE2,
+    },
+    Track {
+        instrument: 0,
+        notes: &VOICE3,
+    },
+    Track {
+        instrument: 0,
+        notes: &VOICE4,
+    },
+];
+static CANON: Song = Song {
+    tempo: 140 / 4,
+    tracks: &CANON_TRACKS,
+};
+
 fn main() -> std::io::Result<()> {
     let mut oscillator = Adsr::new(Oscillator::default(), 10, 300, MAX_VOL / 3 * 2, 10);
     oscillator.set_vol(MAX_VOL / 4);
-    let theme = oscillator.into_player(&softsynth::songs::FRERE_JACQUES);
-    let one_bar = theme.len() / 4;
-    let len = theme.len() * 2 + 3 * one_bar;
-
-    let theme1 = theme.clone().chain(theme.clone());
-    let theme2 = (0..one_bar)
-        .map(|_| 0)
-        .chain(theme.clone())
-        .chain(theme.clone());
-    let theme3 = (0..one_bar * 2)
-        .map(|_| 0)
-        .chain(theme.clone())
-        .chain(theme.clone());
-    let theme4 = (0..one_bar * 3)
-        .map(|_| 0)
-        .chain(theme.clone())
-        .chain(theme.clone());
-
-    let v = mix(mix(theme1, theme2), mix(theme3, theme4));
+    let v = SongPlayer::<_, _, 4>::from_song(&[oscillator], &CANON);
+    let len = v.len();
 
     let stdout = std::io::stdout();
     let mut stdout = stdout.lock();
@@ -37,8 +92,8 @@ fn main() -> std::io::Result<()> {
     stdout.write_u16::<LE>(1)?; // PCM
     stdout.write_u16::<LE>(1)?; // mono
     stdout.write_u32::<LE>(RATE)?; // freq
-    stdout.write_u32::<LE>(RATE * 1 * 16 / 8)?; // bytes/s
-    stdout.write_u16::<LE>(1 * 16 / 8)?; // bytes/block
+    stdout.write_u32::<LE>(RATE * 16 / 8)?; // bytes/s
+    stdout.write_u16::<LE>(16 / 8)?; // bytes/block
     stdout.write_u16::<LE>(16)?; // bits/sample
 
     stdout.write_all(b"data")?;
@@ -1,4 +1,4 @@
-use crate::{compute_ratio, Sound, MAX_VOL, RATE};
+use crate::{compute_ratio, Freq, Sound, MAX_VOL};
 
 #[derive(Debug, Clone)]
 pub struct Adsr<S> {
@@ -7,10 +7,63 @@ pub struct Adsr<S> {
     decay_ms: u32,
     sustain: i16,
     release_ms: u32,
+    curves: [Curve; 3],
     vol: i16,
     sustain_vol: i16,
     state: AdsrState,
 }
+// The shape of a segment of an envelope.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Curve {
+    Linear,
+    // fast at first and then slower, like the decay of a struck string
+    Exponential,
+    // slow at first and then faster
+    Logarithmic,
+    // slow at both ends
+    SCurve,
+}
+const ONE: u32 = 1 << 15;
+impl Curve {
+    // the value going from `from` to `to`, with `ticks` remaining out
+    // of `total`
+    pub(crate) fn ratio(self, from: i16, to: i16, ticks: u32, total: u32) -> i16 {
+        if self == Curve::Linear || total == 0 {
+            return compute_ratio(to, from, ticks, total);
+        }
+        let done = ((total - ticks.min(total)) as u64 * ONE as u64 / total as u64) as u32;
+        compute_ratio(to, from, ONE - self.shape(done), ONE)
+    }
+    // maps the elapsed part of the segment to the part of the way
+    // done, both in Q15
+    fn shape(self, x: u32) -> u32 {
+        match self {
+            Curve::Linear => x,
+            Curve::Exponential => exp(x),
+            Curve::Logarithmic => ONE - exp(ONE - x),
+            Curve::SCurve => {
+                let x = x as u64;
+                ((x * x * (3 * ONE as u64 - 2 * x)) >> 30) as u32
+            }
+        }
+    }
+}
+// (1 - e^(-5x)) / (1 - e^-5), linearly interpolated from a table
+fn exp(x: u32) -> u32 {
+    let idx = (x >> 9) as usize;
+    let frac = x & 0x1ff;
+    let lo = EXP[idx];
+    let hi = EXP[(idx + 1).min(EXP.len() - 1)];
+    lo + (((hi - lo) * frac) >> 9)
+}
+static EXP: [u32; 65] = [
+    0, 2479, 4772, 6893, 8854, 10668, 12345, 13897, 15332, 16659, 17886, 19021, 20071, 21042,
+    21940, 22770, 23538, 24249, 24906, 25513, 26075, 26595, 27075, 27520, 27931, 28311, 28663,
+    28988, 29289, 29567, 29824, 30062, 30282, 30486, 30674, 30848, 31009, 31158, 31296, 31423,
+    31541, 31650, 31750, 31844, 31930, 32010, 32083, 32151, 32214, 32273, 32327, 32377, 32423,
+    32465, 32505, 32541, 32575, 32606, 32635, 32662, 32686, 32709, 32730, 32750, 32768,
+];
+
 #[derive(Copy, Clone, Debug)]
 enum AdsrState {
     Stop,
@@ -20,10 +73,10 @@ enum AdsrState {
     Release { from_vol: i16, ticks: u32 },
 }
 
-fn as_ticks(ms: u32) -> u32 {
-    ms * RATE / 1000
-}
 impl<S: Sound> Adsr<S> {
+    fn as_ticks(&self, ms: u32) -> u32 {
+        ms * self.sound.rate() / 1000
+    }
     pub fn new(mut sound: S, attack_ms: u32, decay_ms: u32, sustain: i16, release_ms: u32) -> Self {
         sound.set_vol(0);
         sound.stop();
@@ -33,6 +86,7 @@ impl<S: Sound> Adsr<S> {
             decay_ms,
             sustain,
             release_ms,
+            curves: [Curve::Linear; 3],
             vol: MAX_VOL,
             sustain_vol: 0,
             state: AdsrState::Stop,
@@ -40,19 +94,41 @@ impl<S: Sound> Adsr<S> {
         res.set_vol(MAX_VOL);
         res
     }
+    pub fn with_curves(mut self, attack: Curve, decay: Curve, release: Curve) -> Self {
+        self.curves = [attack, decay, release];
+        self
+    }
+    pub fn sound(&self) -> &S {
+        &self.sound
+    }
 }
 
 impl<S: Sound> Sound for Adsr<S> {
     fn vol(&self) -> i16 {
         self.vol
     }
-    fn set_freq(&mut self, freq: u16) {
+    fn level(&self) -> i16 {
+        self.sound.vol()
+    }
+    fn rate(&self) -> u32 {
+        self.sound.rate()
+    }
+    fn set_freq(&mut self, freq: Freq) {
         self.sound.set_freq(freq);
         self.state = AdsrState::Attack {
             from_vol: self.sound.vol(),
-            ticks: as_ticks(self.attack_ms),
+            ticks: self.as_ticks(self.attack_ms),
         };
     }
+    fn bend(&mut self, freq: Freq) {
+        self.sound.bend(freq);
+    }
+    fn sync(&mut self) {
+        self.sound.sync();
+    }
+    fn set_phase(&mut self, phase: u32) {
+        self.sound.set_phase(phase);
+    }
     fn get(&self) -> i16 {
         self.sound.get()
     }
@@ -61,10 +137,11 @@ impl<S: Sound> Sound for Adsr<S> {
         match self.state {
             Stop => {}
             Attack { from_vol, ticks } => {
-                let vol = compute_ratio(self.vol, from_vol, ticks, as_ticks(self.attack_ms));
+                let total = self.as_ticks(self.attack_ms);
+                let vol = self.curves[0].ratio(from_vol, self.vol, ticks, total);
                 self.sound.set_vol(vol);
                 self.state = match ticks {
-                    0 => Decay(as_ticks(self.decay_ms)),
+                    0 => Decay(self.as_ticks(self.decay_ms)),
                     ticks => Attack {
                         from_vol,
                         ticks: ticks - 1,
@@ -72,7 +149,8 @@ impl<S: Sound> Sound for Adsr<S> {
                 }
             }
             Decay(ticks) => {
-                let vol = compute_ratio(self.sustain_vol, self.vol, ticks, as_ticks(self.decay_ms));
+                let total = self.as_ticks(self.decay_ms);
+                let vol = self.curves[1].ratio(self.vol, self.sustain_vol, ticks, total);
                 self.sound.set_vol(vol);
                 self.state = match ticks {
                     0 => Sustain,
@@ -81,7 +159,8 @@ impl<S: Sound> Sound for Adsr<S> {
             }
             Sustain => {}
             Release { from_vol, ticks } => {
-                let vol = compute_ratio(0, from_vol, ticks, as_ticks(self.release_ms));
+                let total = self.as_ticks(self.release_ms);
+                let vol = self.curves[2].ratio(from_vol, 0, ticks, total);
                 self.sound.set_vol(vol);
                 self.state = match ticks {
                     0 => {
@@ -100,7 +179,7 @@ impl<S: Sound> Sound for Adsr<S> {
     fn stop(&mut self) {
         self.state = AdsrState::Release {
             from_vol: self.sound.vol(),
-            ticks: as_ticks(self.release_ms),
+            ticks: self.as_ticks(self.release_ms),
         }
     }
     fn set_vol(&mut self, vol: i16) {
@@ -108,3 +187,45 @@ impl<S: Sound> Sound for Adsr<S> {
         self.sustain_vol = (self.vol as i32 * self.sustain as i32 / MAX_VOL as i32) as i16
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CURVES: [Curve; 4] = [
+        Curve::Linear,
+        Curve::Exponential,
+        Curve::Logarithmic,
+        Curve::SCurve,
+    ];
+
+    #[test]
+    fn endpoints_and_monotonicity() {
+        let total = 1000;
+        for &curve in CURVES.iter() {
+            for &(from, to) in &[(0, MAX_VOL), (MAX_VOL, 0), (-MAX_VOL, MAX_VOL / 3)] {
+                assert_eq!(curve.ratio(from, to, total, total), from);
+                assert_eq!(curve.ratio(from, to, 0, total), to);
+                let mut last = from;
+                for ticks in (0..total).rev() {
+                    let v = curve.ratio(from, to, ticks, total);
+                    assert!(from <= to && v >= last || from > to && v <= last);
+                    last = v;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn shapes() {
+        let half = |curve: Curve| curve.ratio(0, MAX_VOL, 500, 1000);
+        assert_eq!(half(Curve::Linear), MAX_VOL / 2 + 1);
+        // 1 - e^-2.5 of the way, once normalized
+        assert_eq!(half(Curve::Exponential), 30_282);
+        // its mirror image
+        assert_eq!(half(Curve::Logarithmic), 2_486);
+        assert_eq!(half(Curve::SCurve), MAX_VOL / 2 + 1);
+        // beyond the end of the segment
+        assert_eq!(Curve::Exponential.ratio(0, MAX_VOL, 2000, 1000), 0);
+    }
+}
@@ -0,0 +1,307 @@
+use crate::{Freq, MAX_VOL, RATE};
+
+// Band-limited classic waveforms using PolyBLEP (for the
+// discontinuities of the saw and the square) and PolyBLAMP (for the
+// corners of the triangle). The phase is a 32-bit accumulator where
+// 2^32 is a full period, and the corrections are computed in Q15.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Waveform {
+    Saw,
+    Square,
+    Triangle,
+}
+
+#[derive(Clone, Debug)]
+pub struct BandLimited {
+    pub waveform: Waveform,
+    vol: i16,
+    freq: Freq,
+    phase: BlepPhase,
+}
+impl BandLimited {
+    pub fn new(waveform: Waveform) -> Self {
+        Self::with_rate(waveform, RATE)
+    }
+    pub fn with_rate(waveform: Waveform, rate: u32) -> Self {
+        Self {
+            waveform,
+            vol: MAX_VOL,
+            freq: Freq(0),
+            phase: BlepPhase::new(rate),
+        }
+    }
+    pub fn freq(&self) -> Freq {
+        self.freq
+    }
+}
+impl crate::Sound for BandLimited {
+    fn vol(&self) -> i16 {
+        self.vol
+    }
+    fn rate(&self) -> u32 {
+        self.phase.rate
+    }
+    fn set_freq(&mut self, freq: Freq) {
+        self.freq = freq;
+        self.phase.set_freq(freq);
+    }
+    fn bend(&mut self, freq: Freq) {
+        if self.phase.is_running() {
+            self.set_freq(freq);
+        }
+    }
+    fn sync(&mut self) {
+        self.phase.sync();
+    }
+    fn set_phase(&mut self, phase: u32) {
+        self.phase.set_phase(phase);
+    }
+    fn set_vol(&mut self, vol: i16) {
+        self.vol = vol;
+    }
+    fn get(&self) -> i16 {
+        let BlepPhase { phase, inc, .. } = self.phase;
+        if inc == 0 {
+            return 0;
+        }
+        let res = match self.waveform {
+            Waveform::Saw => saw(phase, inc),
+            Waveform::Square => square(phase, inc),
+            Waveform::Triangle => triangle(phase, inc),
+        };
+        let res = res.max(-MAX_VOL as i32).min(MAX_VOL as i32);
+        (res * self.vol as i32 / MAX_VOL as i32) as i16
+    }
+    fn advance(&mut self) {
+        self.phase.advance();
+    }
+    fn stop(&mut self) {
+        self.phase.stop();
+    }
+}
+
+// The phase of a band-limited waveform, shared with the pulse wave.
+#[derive(Clone, Debug)]
+pub(crate) struct BlepPhase {
+    pub(crate) rate: u32,
+    // 2^32 is a full period
+    pub(crate) phase: u32,
+    pub(crate) inc: u32,
+}
+impl BlepPhase {
+    pub(crate) fn new(rate: u32) -> Self {
+        BlepPhase {
+            rate,
+            phase: 0,
+            inc: 0,
+        }
+    }
+    pub(crate) fn is_running(&self) -> bool {
+        self.inc != 0
+    }
+    pub(crate) fn set_freq(&mut self, freq: Freq) {
+        self.inc = (((freq.mhz() as u64) << 32) / (self.rate as u64 * 1000)) as u32;
+    }
+    pub(crate) fn sync(&mut self) {
+        self.phase = 0;
+    }
+    pub(crate) fn set_phase(&mut self, phase: u32) {
+        self.phase = phase;
+    }
+    pub(crate) fn advance(&mut self) {
+        self.phase = self.phase.wrapping_add(self.inc);
+    }
+    pub(crate) fn stop(&mut self) {
+        *self = BlepPhase::new(self.rate);
+    }
+}
+
+const ONE: i32 = 1 << 15;
+const HALF_PERIOD: u32 = 1 << 31;
+
+// `num / inc` in Q15, for `num <= inc`. From 2^16, about 1 Hz at
+// 48 kHz, dropping the 15 low bits of `inc` keeps enough precision
+// without needing a 64-bit division; the sub-hertz frequencies need
+// one.
+fn ratio(num: u32, inc: u32) -> i32 {
+    let res = if inc >= 1 << 16 {
+        num / (inc >> 15)
+    } else {
+        (((num as u64) << 15) / inc.max(1) as u64) as u32
+    };
+    res.min(ONE as u32) as i32
+}
+
+pub(crate) fn poly_blep(phase: u32, inc: u32) -> i32 {
+    if phase < inc {
+        let x = ratio(phase, inc);
+        2 * x - ((x * x) >> 15) - ONE
+    } else if phase > inc.wrapping_neg() {
+        let x = ONE - ratio(phase.wrapping_neg(), inc);
+        (x * x) >> 15
+    } else {
+        0
+    }
+}
+
+pub(crate) fn poly_blamp(phase: u32, inc: u32) -> i32 {
+    if phase < inc {
+        let x = ONE - ratio(phase, inc);
+        ((x * x) >> 15) * x / (3 * ONE)
+    } else if phase > inc.wrapping_neg() {
+        let x = ONE - ratio(phase.wrapping_neg(), inc);
+        ((x * x) >> 15) * x / (3 * ONE)
+    } else {
+        0
+    }
+}
+
+fn saw(phase: u32, inc: u32) -> i32 {
+    (phase >> 16) as i32 - ONE - poly_blep(phase, inc)
+}
+
+fn square(phase: u32, inc: u32) -> i32 {
+    let naive = if phase < HALF_PERIOD { ONE } else { -ONE };
+    naive + poly_blep(phase, inc) - poly_blep(phase.wrapping_add(HALF_PERIOD), inc)
+}
+
+fn triangle(phase: u32, inc: u32) -> i32 {
+    // 1 - 4 * |t - 1/2|, the slope changes by 8 at each corner
+    let dist = (phase as i64 - HALF_PERIOD as i64).abs();
+    let naive = ONE - (dist >> 15) as i32;
+    let corners = poly_blamp(phase, inc) - poly_blamp(phase.wrapping_add(HALF_PERIOD), inc);
+    naive + ((8 * inc as i64 * corners as i64) >> 32) as i32
+}
+
+// duties of the pulse wave, in thousandths of the period, as on the
+// NES and the Game Boy
+pub const DUTY_12_5: u16 = 125;
+pub const DUTY_25: u16 = 250;
+pub const DUTY_50: u16 = 500;
+
+// A band-limited pulse wave, high during the duty of the period and
+// centered on 0. The duty is modulated (PWM) by adding an offset, for
+// example with `modulate` as a `Target::Param`.
+#[derive(Clone, Debug)]
+pub struct Pulse {
+    vol: i16,
+    freq: Freq,
+    duty: u16,
+    offset: i16,
+    phase: BlepPhase,
+}
+impl Pulse {
+    pub fn new(duty: u16) -> Self {
+        Self::with_rate(duty, RATE)
+    }
+    pub fn with_rate(duty: u16, rate: u32) -> Self {
+        Self {
+            vol: MAX_VOL,
+            freq: Freq(0),
+            duty,
+            offset: 0,
+            phase: BlepPhase::new(rate),
+        }
+    }
+    pub fn freq(&self) -> Freq {
+        self.freq
+    }
+    pub fn duty(&self) -> u16 {
+        self.duty
+    }
+    pub fn set_duty(&mut self, duty: u16) {
+        self.duty = duty;
+    }
+    // moves the duty by `offset` thousandths of the period
+    pub fn modulate(&mut self, offset: i16) {
+        self.offset = offset;
+    }
+    // the end of the duty, where 2^32 is the period
+    fn edge(&self) -> u32 {
+        let duty = (self.duty as i32 + self.offset as i32).clamp(1, 999) as u64;
+        ((duty << 32) / 1000) as u32
+    }
+}
+impl crate::Sound for Pulse {
+    fn vol(&self) -> i16 {
+        self.vol
+    }
+    fn rate(&self) -> u32 {
+        self.phase.rate
+    }
+    fn set_freq(&mut self, freq: Freq) {
+        self.freq = freq;
+        self.phase.set_freq(freq);
+    }
+    fn bend(&mut self, freq: Freq) {
+        if self.phase.is_running() {
+            self.set_freq(freq);
+        }
+    }
+    fn sync(&mut self) {
+        self.phase.sync();
+    }
+    fn set_phase(&mut self, phase: u32) {
+        self.phase.set_phase(phase);
+    }
+    fn set_vol(&mut self, vol: i16) {
+        self.vol = vol;
+    }
+    fn get(&self) -> i16 {
+        if !self.phase.is_running() {
+            return 0;
+        }
+        let res = pulse(self.phase.phase, self.phase.inc, self.edge());
+        let res = res.max(-MAX_VOL as i32).min(MAX_VOL as i32);
+        (res * self.vol as i32 / MAX_VOL as i32) as i16
+    }
+    fn advance(&mut self) {
+        self.phase.advance();
+    }
+    fn stop(&mut self) {
+        self.phase.stop();
+    }
+}
+
+// Without its DC offset, 2 duty - 1, and scaled so that its longest
+// side is at full scale.
+fn pulse(phase: u32, inc: u32, edge: u32) -> i32 {
+    let naive = if phase < edge { ONE } else { -ONE };
+    let res = naive + poly_blep(phase, inc) - poly_blep(phase.wrapping_sub(edge), inc);
+    let dc = ((2 * edge as i64 - (1 << 32)) >> 17) as i32;
+    (res - dc) * ONE / (ONE + dc.abs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Sound;
+
+    #[test]
+    fn sub_hertz_ratio() {
+        // 0.1 Hz at 48 kHz
+        let inc = 8948;
+        assert_eq!(ratio(0, inc), 0);
+        assert_eq!(ratio(inc / 2, inc), ONE / 2);
+        assert_eq!(ratio(inc, inc), ONE);
+        assert_eq!(ratio(1 << 20, 1 << 21), ONE / 2);
+    }
+
+    #[test]
+    fn pulse_without_dc() {
+        for duty in [DUTY_12_5, DUTY_25, DUTY_50, 800] {
+            let mut pulse = Pulse::new(duty);
+            pulse.set_freq(Freq::from_hz(480));
+            let (mut sum, mut max) = (0i64, 0);
+            for _ in 0..RATE / 480 * 10 {
+                let x = pulse.step();
+                sum += x as i64;
+                max = max.max(x.saturating_abs());
+            }
+            let mean = sum / (RATE / 480 * 10) as i64;
+            assert!(mean.abs() < 100, "duty {}: mean {}", duty, mean);
+            assert!(max > MAX_VOL - 100, "duty {}: max {}", duty, max);
+        }
+    }
+}
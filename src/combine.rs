@@ -0,0 +1,241 @@
+use crate::{Freq, Sound, MAX_VOL};
+
+// Sounds made of two oscillators driven by the same note, the second
+// one at a ratio of its frequency.
+//
+// With a hard sync, a master period, at the frequency of the note,
+// restarts the period of the slave. The pitch heard is the one of the
+// master, and the ratio (or its modulation in cents) only changes the
+// timbre, as in the sync leads.
+
+#[derive(Clone, Debug)]
+pub struct HardSync<S> {
+    slave: S,
+    num: u32,
+    den: u32,
+    cents: i32,
+    freq: Freq,
+    // master phase, 2^32 is a full period
+    phase: u32,
+    inc: u32,
+}
+impl<S: Sound> HardSync<S> {
+    pub fn new(slave: S, num: u32, den: u32) -> Self {
+        HardSync {
+            slave,
+            num,
+            den,
+            cents: 0,
+            freq: Freq(0),
+            phase: 0,
+            inc: 0,
+        }
+    }
+    pub fn slave(&self) -> &S {
+        &self.slave
+    }
+    pub fn set_ratio(&mut self, num: u32, den: u32) {
+        self.num = num;
+        self.den = den;
+        self.update();
+    }
+    // detunes the slave, for sync sweeps
+    pub fn modulate(&mut self, cents: i16) {
+        self.cents = cents as i32;
+        self.update();
+    }
+    fn slave_freq(&self) -> Freq {
+        self.freq
+            .mul_ratio(self.num, self.den)
+            .add_cents(self.cents)
+    }
+    fn set_master(&mut self, freq: Freq) {
+        self.freq = freq;
+        let inc = ((freq.mhz() as u64) << 32) / (self.slave.rate() as u64 * 1000);
+        self.inc = inc.min(u32::MAX as u64) as u32;
+    }
+    fn update(&mut self) {
+        if self.inc != 0 {
+            self.slave.bend(self.slave_freq());
+        }
+    }
+}
+
+impl<S: Sound> Sound for HardSync<S> {
+    fn vol(&self) -> i16 {
+        self.slave.vol()
+    }
+    fn level(&self) -> i16 {
+        self.slave.level()
+    }
+    fn rate(&self) -> u32 {
+        self.slave.rate()
+    }
+    fn get(&self) -> i16 {
+        self.slave.get()
+    }
+    fn advance(&mut self) {
+        self.slave.advance();
+        let (phase, wrapped) = self.phase.overflowing_add(self.inc);
+        self.phase = phase;
+        if wrapped {
+            self.slave.sync();
+        }
+    }
+    fn set_freq(&mut self, freq: Freq) {
+        self.phase = 0;
+        self.set_master(freq);
+        self.slave.set_freq(self.slave_freq());
+    }
+    fn bend(&mut self, freq: Freq) {
+        self.set_master(freq);
+        self.slave.bend(self.slave_freq());
+    }
+    fn sync(&mut self) {
+        self.phase = 0;
+        self.slave.sync();
+    }
+    // the slave at the same point of the master period: as many of its
+    // periods as the ratio since the last sync
+    fn set_phase(&mut self, phase: u32) {
+        self.phase = phase;
+        let ratio = match self.freq.mhz() {
+            0 => ((self.num as u64) << 32) / self.den.max(1) as u64,
+            mhz => ((self.slave_freq().mhz() as u64) << 32) / mhz as u64,
+        };
+        self.slave
+            .set_phase(((phase as u128 * ratio as u128) >> 32) as u32);
+    }
+    // the master keeps running, so that the slave stays in sync during
+    // its release
+    fn stop(&mut self) {
+        self.slave.stop();
+    }
+    fn set_vol(&mut self, vol: i16) {
+        self.slave.set_vol(vol);
+    }
+}
+
+// A ring modulation multiplies the two signals, giving their sum and
+// difference frequencies: with an inharmonic ratio, metallic tones. An
+// amplitude modulation keeps the carrier, the modulator only driving
+// its volume between 0 and full.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RingMode {
+    Ring,
+    Am,
+}
+
+#[derive(Clone, Debug)]
+pub struct RingMod<C, M> {
+    pub mode: RingMode,
+    carrier: C,
+    modulator: M,
+    num: u32,
+    den: u32,
+    freq: Freq,
+    vol: i16,
+}
+impl<C: Sound, M: Sound> RingMod<C, M> {
+    pub fn new(carrier: C, modulator: M, mode: RingMode, num: u32, den: u32) -> Self {
+        RingMod {
+            mode,
+            carrier,
+            modulator,
+            num,
+            den,
+            freq: Freq(0),
+            vol: MAX_VOL,
+        }
+    }
+    pub fn carrier(&self) -> &C {
+        &self.carrier
+    }
+    pub fn modulator(&self) -> &M {
+        &self.modulator
+    }
+    pub fn set_ratio(&mut self, num: u32, den: u32) {
+        self.num = num;
+        self.den = den;
+        if self.freq != Freq(0) {
+            self.modulator.bend(self.freq.mul_ratio(num, den));
+        }
+    }
+}
+
+impl<C: Sound, M: Sound> Sound for RingMod<C, M> {
+    fn vol(&self) -> i16 {
+        self.vol
+    }
+    fn level(&self) -> i16 {
+        (self.carrier.level() as i32 * self.vol as i32 / MAX_VOL as i32) as i16
+    }
+    fn rate(&self) -> u32 {
+        self.carrier.rate()
+    }
+    fn get(&self) -> i16 {
+        let carrier = self.carrier.get() as i32;
+        let modulator = self.modulator.get() as i32;
+        let res = match self.mode {
+            RingMode::Ring => carrier * modulator / MAX_VOL as i32,
+            RingMode::Am => carrier * ((modulator + MAX_VOL as i32) / 2) / MAX_VOL as i32,
+        };
+        (res * self.vol as i32 / MAX_VOL as i32) as i16
+    }
+    fn advance(&mut self) {
+        self.carrier.advance();
+        self.modulator.advance();
+    }
+    fn set_freq(&mut self, freq: Freq) {
+        self.freq = freq;
+        self.carrier.set_freq(freq);
+        self.modulator.set_freq(freq.mul_ratio(self.num, self.den));
+    }
+    fn bend(&mut self, freq: Freq) {
+        self.freq = freq;
+        self.carrier.bend(freq);
+        self.modulator.bend(freq.mul_ratio(self.num, self.den));
+    }
+    fn sync(&mut self) {
+        self.carrier.sync();
+        self.modulator.sync();
+    }
+    fn set_phase(&mut self, phase: u32) {
+        self.carrier.set_phase(phase);
+        self.modulator.set_phase(phase);
+    }
+    fn stop(&mut self) {
+        self.carrier.stop();
+        self.modulator.stop();
+    }
+    fn set_vol(&mut self, vol: i16) {
+        self.vol = vol;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Adsr, Oscillator};
+
+    #[test]
+    fn synced_during_release() {
+        let slave = Adsr::new(Oscillator::default(), 1, 1, MAX_VOL, 100);
+        let mut sync = HardSync::new(slave, 5, 2);
+        sync.set_freq(Freq::from_hz(480));
+        let period = (crate::RATE / 480) as usize;
+        for _ in 0..period * 10 {
+            sync.step();
+        }
+        sync.stop();
+        // still the period of the master
+        let mut samples = [0i16; 400];
+        for s in samples.iter_mut() {
+            *s = sync.step();
+        }
+        for i in 0..samples.len() - period {
+            let (a, b) = (samples[i] as i32, samples[i + period] as i32);
+            assert!((a - b).abs() <= a.abs() / 8 + 64, "{} {}", a, b);
+        }
+    }
+}
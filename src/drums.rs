@@ -0,0 +1,246 @@
+use crate::modulation::CONTROL_TICKS;
+use crate::noise::{Noise, NoiseColor};
+use crate::pitch::{self, AS2, C2, D2, DS2, FS2};
+use crate::{Curve, Env, FilterMode, Freq, Oscillator, Sound, Stage, Svf, MAX_VOL, RATE};
+
+// Synthesized drums: each voice mixes a sine tone, with a pitch
+// envelope, and a filtered noise, each one with its own envelope.
+//
+// A `Kit` plays the drums from the General MIDI percussion notes, so a
+// score of a percussion track is made of the following pitches.
+
+pub const KICK: Freq = C2;
+pub const SNARE: Freq = D2;
+pub const CLAP: Freq = DS2;
+pub const CLOSED_HAT: Freq = FS2;
+pub const OPEN_HAT: Freq = AS2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Drum {
+    Kick,
+    Snare,
+    Clap,
+    ClosedHat,
+    OpenHat,
+}
+impl Drum {
+    pub fn from_midi(note: u8) -> Option<Self> {
+        match note {
+            35 | 36 => Some(Drum::Kick),
+            37 | 38 | 40 => Some(Drum::Snare),
+            39 => Some(Drum::Clap),
+            42 | 44 => Some(Drum::ClosedHat),
+            46 => Some(Drum::OpenHat),
+            _ => None,
+        }
+    }
+}
+
+const EXP: Curve = Curve::Exponential;
+
+#[derive(Clone, Debug)]
+pub struct DrumVoice {
+    pub drum: Drum,
+    tone: Oscillator,
+    noise: Svf<Noise>,
+    tone_env: Env,
+    noise_env: Env,
+    pitch_env: Env,
+    // pitch of the tone at the top and at the bottom of its envelope
+    high: Freq,
+    low: Freq,
+    vol: i16,
+    ticks: u32,
+}
+impl DrumVoice {
+    pub fn new(drum: Drum) -> Self {
+        Self::with_rate(drum, RATE)
+    }
+    pub fn with_rate(drum: Drum, rate: u32) -> Self {
+        let (high, low) = match drum {
+            Drum::Kick => (Freq::from_hz(150), Freq::from_hz(45)),
+            _ => (Freq::from_hz(220), Freq::from_hz(160)),
+        };
+        let (mode, cutoff, q) = match drum {
+            Drum::Kick => (FilterMode::LowPass, 4000, 707),
+            Drum::Snare => (FilterMode::HighPass, 1500, 707),
+            Drum::Clap => (FilterMode::BandPass, 1200, 1500),
+            Drum::ClosedHat | Drum::OpenHat => (FilterMode::HighPass, 7000, 707),
+        };
+        let tone_env: &[Stage] = match drum {
+            Drum::Kick => &[Stage::new(0, MAX_VOL), Stage::new(400, 0).with_curve(EXP)],
+            Drum::Snare => &[
+                Stage::new(0, MAX_VOL / 2),
+                Stage::new(120, 0).with_curve(EXP),
+            ],
+            _ => &[],
+        };
+        let noise_env: &[Stage] = match drum {
+            Drum::Kick => &[Stage::new(0, MAX_VOL / 4), Stage::new(10, 0)],
+            Drum::Snare => &[Stage::new(0, MAX_VOL), Stage::new(180, 0).with_curve(EXP)],
+            Drum::Clap => &[
+                Stage::new(0, MAX_VOL),
+                Stage::new(10, MAX_VOL / 5),
+                Stage::new(0, MAX_VOL),
+                Stage::new(10, MAX_VOL / 5),
+                Stage::new(0, MAX_VOL),
+                Stage::new(200, 0).with_curve(EXP),
+            ],
+            Drum::ClosedHat => &[Stage::new(0, MAX_VOL), Stage::new(50, 0).with_curve(EXP)],
+            Drum::OpenHat => &[
+                Stage::new(0, MAX_VOL),
+                Stage::new(500, 0).with_curve(EXP),
+                // choked by a closed hat
+                Stage::new(20, 0),
+            ],
+        };
+        let pitch_ms = match drum {
+            Drum::Kick => 60,
+            _ => 30,
+        };
+        let mut noise_env = Env::with_rate(noise_env, rate);
+        if drum == Drum::OpenHat {
+            noise_env = noise_env.with_sustain(1);
+        }
+        DrumVoice {
+            drum,
+            tone: Oscillator::with_rate(rate),
+            noise: Svf::new(
+                Noise::with_rate(NoiseColor::White, rate),
+                mode,
+                Freq::from_hz(cutoff),
+                q,
+            ),
+            tone_env: Env::with_rate(tone_env, rate),
+            noise_env,
+            pitch_env: Env::with_rate(&[Stage::new(0, MAX_VOL), Stage::new(pitch_ms, 0)], rate),
+            high,
+            low,
+            vol: MAX_VOL,
+            ticks: 0,
+        }
+    }
+    fn update_pitch(&mut self) {
+        let (high, low) = (self.high.mhz() as u64, self.low.mhz() as u64);
+        let mhz = low + (high - low) * self.pitch_env.level().max(0) as u64 / MAX_VOL as u64;
+        self.tone.bend(Freq(mhz as u32));
+    }
+}
+
+impl Sound for DrumVoice {
+    fn vol(&self) -> i16 {
+        self.vol
+    }
+    fn level(&self) -> i16 {
+        let level = self.tone_env.level().max(self.noise_env.level()) as i32;
+        (level * self.vol as i32 / MAX_VOL as i32) as i16
+    }
+    fn rate(&self) -> u32 {
+        self.tone.rate()
+    }
+    fn get(&self) -> i16 {
+        let tone = self.tone.get() as i32 * self.tone_env.level() as i32 / MAX_VOL as i32;
+        let noise = self.noise.get() as i32 * self.noise_env.level() as i32 / MAX_VOL as i32;
+        // half of the full scale for each, to mix them without clipping
+        let res = (tone + noise) / 2 * self.vol as i32 / MAX_VOL as i32;
+        res.clamp(i16::MIN as i32, i16::MAX as i32) as i16
+    }
+    fn advance(&mut self) {
+        self.tone_env.advance();
+        self.noise_env.advance();
+        self.pitch_env.advance();
+        self.ticks += 1;
+        if self.ticks >= CONTROL_TICKS {
+            self.ticks = 0;
+            self.update_pitch();
+        }
+        self.tone.advance();
+        self.noise.advance();
+    }
+    // Any note hits the drum, its frequency is ignored.
+    fn set_freq(&mut self, _freq: Freq) {
+        self.tone.stop();
+        self.tone.set_freq(self.high);
+        let rate = self.rate();
+        self.noise.set_freq(Freq(rate * 1000));
+        self.tone_env.gate_on();
+        self.noise_env.gate_on();
+        self.pitch_env.gate_on();
+        self.ticks = 0;
+    }
+    fn bend(&mut self, _freq: Freq) {}
+    fn stop(&mut self) {
+        self.tone_env.gate_off();
+        self.noise_env.gate_off();
+        self.pitch_env.gate_off();
+    }
+    fn set_vol(&mut self, vol: i16) {
+        self.vol = vol;
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Kit {
+    voices: [DrumVoice; 5],
+    vol: i16,
+}
+impl Kit {
+    pub fn new() -> Self {
+        Self::with_rate(RATE)
+    }
+    pub fn with_rate(rate: u32) -> Self {
+        use Drum::*;
+        Kit {
+            voices: [Kick, Snare, Clap, ClosedHat, OpenHat].map(|d| DrumVoice::with_rate(d, rate)),
+            vol: MAX_VOL,
+        }
+    }
+    pub fn hit(&mut self, drum: Drum) {
+        if drum == Drum::ClosedHat {
+            self.voices[Drum::OpenHat as usize].stop();
+        }
+        self.voices[drum as usize].set_freq(Freq(0));
+    }
+}
+impl Default for Kit {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sound for Kit {
+    fn vol(&self) -> i16 {
+        self.vol
+    }
+    fn level(&self) -> i16 {
+        self.voices.iter().map(|v| v.level()).max().unwrap_or(0)
+    }
+    fn rate(&self) -> u32 {
+        self.voices[0].rate()
+    }
+    fn get(&self) -> i16 {
+        let sum = self
+            .voices
+            .iter()
+            .fold(0i16, |acc, v| acc.saturating_add(v.get()));
+        (sum as i32 * self.vol as i32 / MAX_VOL as i32) as i16
+    }
+    fn advance(&mut self) {
+        for voice in self.voices.iter_mut() {
+            voice.advance();
+        }
+    }
+    // the General MIDI percussion note of the frequency
+    fn set_freq(&mut self, freq: Freq) {
+        if let Some(drum) = Drum::from_midi(pitch::midi_note(freq)) {
+            self.hit(drum);
+        }
+    }
+    fn bend(&mut self, _freq: Freq) {}
+    // the drums always ring until their end, only a closed hat chokes
+    // an open hat
+    fn stop(&mut self) {}
+    fn set_vol(&mut self, vol: i16) {
+        self.vol = vol;
+    }
+}
@@ -0,0 +1,293 @@
+use crate::{Curve, Freq, Modulator, Sound, MAX_VOL, RATE};
+
+// A multi-stage envelope: a list of breakpoints, each one reached from
+// the previous level in a given time and along a given curve. While
+// the gate is on, the envelope stops at the end of the sustain stage,
+// or loops back to the loop stage if any. When the gate goes off, it
+// continues with the stages after the sustain stage. Without sustain
+// stage, the whole envelope is played at each gate on.
+//
+// `Env` only generates the levels, so it can drive any parameter;
+// `Envelope` uses it to drive the volume of a sound.
+
+pub const MAX_STAGES: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Stage {
+    pub ms: u32,
+    pub level: i16,
+    pub curve: Curve,
+}
+impl Stage {
+    pub const fn new(ms: u32, level: i16) -> Self {
+        Stage {
+            ms,
+            level,
+            curve: Curve::Linear,
+        }
+    }
+    pub const fn with_curve(self, curve: Curve) -> Self {
+        Stage { curve, ..self }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Env {
+    stages: [Stage; MAX_STAGES],
+    len: usize,
+    sustain: Option<usize>,
+    loop_start: Option<usize>,
+    rate: u32,
+    gate: bool,
+    // current stage, `len` when finished
+    stage: usize,
+    from: i16,
+    level: i16,
+    ticks: u32,
+    total: u32,
+}
+impl Env {
+    // Stages beyond MAX_STAGES are ignored.
+    pub fn new(stages: &[Stage]) -> Self {
+        Self::with_rate(stages, RATE)
+    }
+    pub fn with_rate(stages: &[Stage], rate: u32) -> Self {
+        let mut res = Env {
+            stages: [Stage::new(0, 0); MAX_STAGES],
+            len: stages.len().min(MAX_STAGES),
+            sustain: None,
+            loop_start: None,
+            rate,
+            gate: false,
+            stage: 0,
+            from: 0,
+            level: 0,
+            ticks: 0,
+            total: 0,
+        };
+        res.stages[..res.len].copy_from_slice(&stages[..res.len]);
+        res.stage = res.len;
+        res
+    }
+    pub fn dahdsr(
+        delay_ms: u32,
+        attack_ms: u32,
+        hold_ms: u32,
+        decay_ms: u32,
+        sustain: i16,
+        release_ms: u32,
+    ) -> Self {
+        Self::new(&[
+            Stage::new(delay_ms, 0),
+            Stage::new(attack_ms, MAX_VOL),
+            Stage::new(hold_ms, MAX_VOL),
+            Stage::new(decay_ms, sustain),
+            Stage::new(release_ms, 0),
+        ])
+        .with_sustain(3)
+    }
+    pub fn with_sustain(mut self, stage: usize) -> Self {
+        self.sustain = Some(stage).filter(|&s| s < self.len);
+        self
+    }
+    // loops from the end of the sustain stage back to `stage`
+    pub fn with_loop(mut self, stage: usize) -> Self {
+        self.loop_start = Some(stage).filter(|&s| s < self.len);
+        self
+    }
+    pub fn level(&self) -> i16 {
+        self.level
+    }
+    pub fn is_done(&self) -> bool {
+        self.stage >= self.len
+    }
+    pub fn gate_on(&mut self) {
+        self.gate = true;
+        self.enter(0);
+    }
+    pub fn gate_off(&mut self) {
+        self.gate = false;
+        match self.sustain {
+            Some(sustain) if self.stage <= sustain => self.enter(sustain + 1),
+            _ => {}
+        }
+    }
+    pub fn advance(&mut self) {
+        let stage = match self.stages[..self.len].get(self.stage) {
+            None => return,
+            Some(stage) => *stage,
+        };
+        self.level = stage
+            .curve
+            .ratio(self.from, stage.level, self.ticks, self.total);
+        if self.ticks > 0 {
+            self.ticks -= 1;
+        } else if !self.gate || self.sustain != Some(self.stage) {
+            self.enter(self.stage + 1);
+        } else if let Some(start) = self.loop_start {
+            self.enter(start);
+        }
+    }
+    pub fn step(&mut self) -> i16 {
+        let res = self.level;
+        self.advance();
+        res
+    }
+    // starts `stage` from the current level
+    fn enter(&mut self, stage: usize) {
+        self.stage = stage;
+        self.from = self.level;
+        self.total = self.stages[..self.len]
+            .get(stage)
+            .map_or(0, |s| (s.ms as u64 * self.rate as u64 / 1000) as u32);
+        self.ticks = self.total;
+    }
+}
+
+impl Modulator for Env {
+    fn value(&self) -> i16 {
+        self.level
+    }
+    fn advance(&mut self) {
+        Env::advance(self);
+    }
+    fn trigger(&mut self) {
+        self.gate_on();
+    }
+    fn release(&mut self) {
+        self.gate_off();
+    }
+    fn set_rate(&mut self, rate: u32) {
+        self.rate = rate;
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Envelope<S> {
+    sound: S,
+    env: Env,
+    vol: i16,
+}
+impl<S: Sound> Envelope<S> {
+    pub fn new(mut sound: S, mut env: Env) -> Self {
+        sound.set_vol(0);
+        sound.stop();
+        env.set_rate(sound.rate());
+        Envelope {
+            env,
+            sound,
+            vol: MAX_VOL,
+        }
+    }
+    pub fn env(&self) -> &Env {
+        &self.env
+    }
+}
+
+impl<S: Sound> Sound for Envelope<S> {
+    fn vol(&self) -> i16 {
+        self.vol
+    }
+    fn level(&self) -> i16 {
+        self.sound.vol()
+    }
+    fn rate(&self) -> u32 {
+        self.sound.rate()
+    }
+    fn get(&self) -> i16 {
+        self.sound.get()
+    }
+    fn advance(&mut self) {
+        let was_done = self.env.is_done();
+        self.env.advance();
+        let vol = self.vol as i32 * self.env.level() as i32 / MAX_VOL as i32;
+        self.sound.set_vol(vol as i16);
+        if !was_done && self.env.is_done() {
+            self.sound.stop();
+        }
+        self.sound.advance();
+    }
+    fn set_freq(&mut self, freq: Freq) {
+        self.sound.set_freq(freq);
+        self.env.gate_on();
+    }
+    fn bend(&mut self, freq: Freq) {
+        self.sound.bend(freq);
+    }
+    fn sync(&mut self) {
+        self.sound.sync();
+    }
+    fn set_phase(&mut self, phase: u32) {
+        self.sound.set_phase(phase);
+    }
+    fn stop(&mut self) {
+        self.env.gate_off();
+    }
+    fn set_vol(&mut self, vol: i16) {
+        self.vol = vol;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // one tick per ms
+    const MS: u32 = 1000;
+
+    #[test]
+    fn sustain_loop() {
+        let stages = [
+            Stage::new(10, MAX_VOL),
+            Stage::new(10, MAX_VOL / 2),
+            Stage::new(10, MAX_VOL),
+            Stage::new(10, 0),
+        ];
+        let mut env = Env::with_rate(&stages, MS).with_sustain(2).with_loop(1);
+        env.gate_on();
+        for _ in 0..=10 {
+            env.step();
+        }
+        // between the two levels as long as the gate is on
+        let (mut lows, mut highs) = (0, 0);
+        for _ in 0..1000 {
+            let level = env.step();
+            assert!((MAX_VOL / 2..=MAX_VOL).contains(&level));
+            lows += (level == MAX_VOL / 2) as u32;
+            highs += (level == MAX_VOL) as u32;
+        }
+        assert!(lows >= 40 && highs >= 40);
+        env.gate_off();
+        for _ in 0..=10 {
+            env.step();
+        }
+        assert!(env.is_done());
+        assert_eq!(env.level(), 0);
+    }
+
+    #[test]
+    fn release_during_attack() {
+        let stages = [
+            Stage::new(100, MAX_VOL),
+            Stage::new(100, MAX_VOL / 2),
+            Stage::new(50, 0),
+        ];
+        let mut env = Env::with_rate(&stages, MS).with_sustain(1);
+        env.gate_on();
+        for _ in 0..50 {
+            env.step();
+        }
+        let mut last = env.level();
+        assert!((last - MAX_VOL / 2).abs() < MAX_VOL / 50);
+        // the release starts from the current level, without jumping
+        // to the sustain level
+        env.gate_off();
+        for _ in 0..=50 {
+            let level = env.step();
+            assert!(level <= last);
+            last = level;
+        }
+        assert!(env.is_done());
+        assert_eq!(env.level(), 0);
+    }
+}
@@ -0,0 +1,416 @@
+use crate::oscillator::sin;
+use crate::pitch::cents_ratio;
+use crate::{Freq, Sound};
+
+// Resonant filters wrapping a sound. The resonance `q` is given in
+// thousandths (707 for a Butterworth response), and the cutoff can be
+// moved in cents around its base value with `modulate`, for example
+// from an envelope or an LFO.
+//
+// The filtered sample is computed in `advance`, from the current sample
+// of the wrapped sound, so `get` stays cheap but the output lags by one
+// sample.
+// The states keep 8 more bits than the samples to limit the rounding
+// noise at low cutoffs.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FilterMode {
+    LowPass,
+    HighPass,
+    BandPass,
+    Notch,
+}
+
+const EXTRA_BITS: u32 = 8;
+// keeps the states bounded if a filter is unstable
+const MAX_STATE: i64 = 1 << 28;
+
+fn to_sample(x: i64) -> i16 {
+    (x >> EXTRA_BITS).clamp(i16::MIN as i64, i16::MAX as i64) as i16
+}
+
+// the cutoff as a Q32 fraction of the sample rate, below `max`
+fn cutoff_phase(cutoff: Freq, cents: i32, rate: u32, max: u32) -> u32 {
+    let mhz = cutoff.add_cents(cents).mhz() as u64;
+    ((mhz << 32) / (rate as u64 * 1000)).min(max as u64) as u32
+}
+
+// Chamberlin state variable filter, stable up to a sixth of the sample
+// rate.
+#[derive(Clone, Debug)]
+pub struct Svf<S> {
+    sound: S,
+    pub mode: FilterMode,
+    cutoff: Freq,
+    q: u32,
+    cents: i32,
+    // 2 sin(π cutoff / rate) and 1 / q, in Q15
+    f: i64,
+    damping: i64,
+    low: i64,
+    band: i64,
+    out: i16,
+}
+impl<S: Sound> Svf<S> {
+    pub fn new(sound: S, mode: FilterMode, cutoff: Freq, q: u32) -> Self {
+        let mut res = Svf {
+            sound,
+            mode,
+            cutoff,
+            q,
+            cents: 0,
+            f: 0,
+            damping: 0,
+            low: 0,
+            band: 0,
+            out: 0,
+        };
+        res.set_q(q);
+        res
+    }
+    pub fn cutoff(&self) -> Freq {
+        self.cutoff
+    }
+    pub fn set_cutoff(&mut self, cutoff: Freq) {
+        self.cutoff = cutoff;
+        self.update();
+    }
+    pub fn set_q(&mut self, q: u32) {
+        self.q = q.max(500);
+        self.damping = (1 << 15) * 1000 / self.q as i64;
+        self.update();
+    }
+    pub fn modulate(&mut self, cents: i16) {
+        self.cents = cents as i32;
+        self.update();
+    }
+    fn update(&mut self) {
+        let phase = cutoff_phase(self.cutoff, self.cents, self.sound.rate(), u32::MAX / 6);
+        // sin(π x) is sin(2π x/2)
+        self.f = (2 * sin(phase / 2) as i64) >> 15;
+    }
+    fn filter(&mut self, input: i16) -> i16 {
+        let input = (input as i64) << EXTRA_BITS;
+        self.low += (self.f * self.band) >> 15;
+        let high = input - self.low - ((self.damping * self.band) >> 15);
+        self.band += (self.f * high) >> 15;
+        self.low = self.low.clamp(-MAX_STATE, MAX_STATE);
+        self.band = self.band.clamp(-MAX_STATE, MAX_STATE);
+        to_sample(match self.mode {
+            FilterMode::LowPass => self.low,
+            FilterMode::HighPass => high,
+            FilterMode::BandPass => self.band,
+            FilterMode::Notch => high + self.low,
+        })
+    }
+}
+
+impl<S: Sound> Sound for Svf<S> {
+    fn vol(&self) -> i16 {
+        self.sound.vol()
+    }
+    fn level(&self) -> i16 {
+        self.sound.level()
+    }
+    fn rate(&self) -> u32 {
+        self.sound.rate()
+    }
+    fn get(&self) -> i16 {
+        self.out
+    }
+    fn advance(&mut self) {
+        self.out = self.filter(self.sound.get());
+        self.sound.advance();
+    }
+    fn set_freq(&mut self, freq: Freq) {
+        self.sound.set_freq(freq);
+    }
+    fn bend(&mut self, freq: Freq) {
+        self.sound.bend(freq);
+    }
+    fn sync(&mut self) {
+        self.sound.sync();
+    }
+    fn set_phase(&mut self, phase: u32) {
+        self.sound.set_phase(phase);
+    }
+    fn stop(&mut self) {
+        self.sound.stop();
+    }
+    fn set_vol(&mut self, vol: i16) {
+        self.sound.set_vol(vol);
+    }
+}
+
+// Biquad coefficients in Q30, normalized so that a0 is 1.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Coefs {
+    pub b0: i64,
+    pub b1: i64,
+    pub b2: i64,
+    pub a1: i64,
+    pub a2: i64,
+}
+const ONE: i64 = 1 << 30;
+const MAX_GAIN: i16 = 2400;
+impl Coefs {
+    // From the Audio EQ Cookbook, the band pass having a gain of 1 at
+    // the cutoff.
+    pub fn new(mode: FilterMode, cutoff: Freq, q: u32, rate: u32) -> Self {
+        let (cos_w, alpha) = Self::prepare(cutoff, q, rate);
+        let one_minus_cos = ONE - cos_w;
+        let (b0, b1, b2) = match mode {
+            FilterMode::LowPass => (one_minus_cos / 2, one_minus_cos, one_minus_cos / 2),
+            FilterMode::HighPass => {
+                let one_plus_cos = 2 * ONE - one_minus_cos;
+                (one_plus_cos / 2, -one_plus_cos, one_plus_cos / 2)
+            }
+            FilterMode::BandPass => (alpha, 0, -alpha),
+            FilterMode::Notch => (ONE, -2 * cos_w, ONE),
+        };
+        Self::normalize(b0, b1, b2, ONE + alpha, -2 * cos_w, ONE - alpha)
+    }
+    // a peaking equalizer, with a gain in hundredths of dB, at most
+    // ±24 dB so that the coefficients fit
+    pub fn peak(cutoff: Freq, q: u32, gain: i16, rate: u32) -> Self {
+        let gain = gain.clamp(-MAX_GAIN, MAX_GAIN);
+        let (cos_w, alpha) = Self::prepare(cutoff, q, rate);
+        // 10^(dB/40) is 2^(dB/40 log2(10)), 99.658 cents per dB
+        let a = (cents_ratio(gain as i64 * 996_578 / 1000) >> 2) as i64;
+        let alpha_mul = (alpha * a) >> 30;
+        let alpha_div = (alpha << 30) / a.max(1);
+        Self::normalize(
+            ONE + alpha_mul,
+            -2 * cos_w,
+            ONE - alpha_mul,
+            ONE + alpha_div,
+            -2 * cos_w,
+            ONE - alpha_div,
+        )
+    }
+    // cos(w) and alpha, q being at least 500 so that alpha is below 1
+    fn prepare(cutoff: Freq, q: u32, rate: u32) -> (i64, i64) {
+        let phase = cutoff_phase(cutoff, 0, rate, u32::MAX / 2 - u32::MAX / 64);
+        let sin_w = sin(phase) as i64;
+        // cos(w) computed as 1 - 2 sin²(w/2), the poles being very
+        // sensitive to its precision at low cutoffs
+        let sin_half = sin(phase / 2) as i64;
+        let cos_w = ONE - ((2 * sin_half * sin_half) >> 30);
+        let alpha = sin_w * 1000 / (2 * q.max(500) as i64);
+        (cos_w, alpha)
+    }
+    fn normalize(b0: i64, b1: i64, b2: i64, a0: i64, a1: i64, a2: i64) -> Self {
+        let a0 = a0.max(1);
+        Coefs {
+            b0: (b0 << 30) / a0,
+            b1: (b1 << 30) / a0,
+            b2: (b2 << 30) / a0,
+            a1: (a1 << 30) / a0,
+            a2: (a2 << 30) / a0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Design {
+    Mode(FilterMode),
+    // gain in hundredths of dB
+    Peak(i16),
+    Custom,
+}
+
+// Direct form I biquad filter.
+#[derive(Clone, Debug)]
+pub struct Biquad<S> {
+    sound: S,
+    design: Design,
+    cutoff: Freq,
+    q: u32,
+    cents: i32,
+    coefs: Coefs,
+    x: [i64; 2],
+    y: [i64; 2],
+    out: i16,
+}
+impl<S: Sound> Biquad<S> {
+    pub fn new(sound: S, mode: FilterMode, cutoff: Freq, q: u32) -> Self {
+        Self::with_design(sound, Design::Mode(mode), cutoff, q)
+    }
+    pub fn peak(sound: S, cutoff: Freq, q: u32, gain: i16) -> Self {
+        Self::with_design(sound, Design::Peak(gain), cutoff, q)
+    }
+    // a filter that can't be modulated
+    pub fn with_coefs(sound: S, coefs: Coefs) -> Self {
+        let mut res = Self::with_design(sound, Design::Custom, Freq(0), 0);
+        res.coefs = coefs;
+        res
+    }
+    fn with_design(sound: S, design: Design, cutoff: Freq, q: u32) -> Self {
+        let mut res = Biquad {
+            sound,
+            design,
+            cutoff,
+            q,
+            cents: 0,
+            coefs: Coefs::default(),
+            x: [0; 2],
+            y: [0; 2],
+            out: 0,
+        };
+        res.update();
+        res
+    }
+    pub fn coefs(&self) -> Coefs {
+        self.coefs
+    }
+    pub fn cutoff(&self) -> Freq {
+        self.cutoff
+    }
+    pub fn set_cutoff(&mut self, cutoff: Freq) {
+        self.cutoff = cutoff;
+        self.update();
+    }
+    pub fn set_q(&mut self, q: u32) {
+        self.q = q;
+        self.update();
+    }
+    pub fn modulate(&mut self, cents: i16) {
+        self.cents = cents as i32;
+        self.update();
+    }
+    fn update(&mut self) {
+        let cutoff = self.cutoff.add_cents(self.cents);
+        let rate = self.sound.rate();
+        self.coefs = match self.design {
+            Design::Mode(mode) => Coefs::new(mode, cutoff, self.q, rate),
+            Design::Peak(gain) => Coefs::peak(cutoff, self.q, gain, rate),
+            Design::Custom => self.coefs,
+        };
+    }
+    fn filter(&mut self, input: i16) -> i16 {
+        let c = &self.coefs;
+        let x = (input as i64) << EXTRA_BITS;
+        let y =
+            (c.b0 * x + c.b1 * self.x[0] + c.b2 * self.x[1] - c.a1 * self.y[0] - c.a2 * self.y[1])
+                >> 30;
+        let y = y.clamp(-MAX_STATE, MAX_STATE);
+        self.x = [x, self.x[0]];
+        self.y = [y, self.y[0]];
+        to_sample(y)
+    }
+}
+
+impl<S: Sound> Sound for Biquad<S> {
+    fn vol(&self) -> i16 {
+        self.sound.vol()
+    }
+    fn level(&self) -> i16 {
+        self.sound.level()
+    }
+    fn rate(&self) -> u32 {
+        self.sound.rate()
+    }
+    fn get(&self) -> i16 {
+        self.out
+    }
+    fn advance(&mut self) {
+        self.out = self.filter(self.sound.get());
+        self.sound.advance();
+    }
+    fn set_freq(&mut self, freq: Freq) {
+        self.sound.set_freq(freq);
+    }
+    fn bend(&mut self, freq: Freq) {
+        self.sound.bend(freq);
+    }
+    fn sync(&mut self) {
+        self.sound.sync();
+    }
+    fn set_phase(&mut self, phase: u32) {
+        self.sound.set_phase(phase);
+    }
+    fn stop(&mut self) {
+        self.sound.stop();
+    }
+    fn set_vol(&mut self, vol: i16) {
+        self.sound.set_vol(vol);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Oscillator, RATE};
+
+    static DC: [i16; 256] = [8000; 256];
+    // alternates at each sample when played at RATE / 256
+    static NYQUIST: [i16; 256] = {
+        let mut res = [8000; 256];
+        let mut i = 1;
+        while i < 256 {
+            res[i] = -8000;
+            i += 2;
+        }
+        res
+    };
+
+    fn input(sample: &'static [i16; 256]) -> Oscillator {
+        let mut osc = Oscillator::default();
+        osc.sample = sample;
+        osc.set_freq(Freq(RATE * 1000 / 256));
+        osc
+    }
+    // the peak of the output once settled
+    fn peak(mut sound: impl Sound) -> i16 {
+        for _ in 0..RATE / 10 {
+            sound.step();
+        }
+        (0..256)
+            .map(|_| sound.step().saturating_abs())
+            .max()
+            .unwrap()
+    }
+
+    #[test]
+    fn low_and_high_pass() {
+        let cutoff = Freq::from_hz(1000);
+        for &(mode, dc, nyquist) in &[
+            (FilterMode::LowPass, 8000, 0),
+            (FilterMode::HighPass, 0, 8000),
+        ] {
+            let biquad = |s| Biquad::new(input(s), mode, cutoff, 707);
+            assert!((peak(biquad(&DC)) - dc).abs() < 80);
+            assert!((peak(biquad(&NYQUIST)) - nyquist).abs() < 80);
+            let svf = |s| Svf::new(input(s), mode, cutoff, 707);
+            assert!((peak(svf(&DC)) - dc).abs() < 80);
+        }
+        // the state variable filter is only stable up to a sixth of the
+        // sample rate, but still rejects the highest frequencies
+        let svf = Svf::new(input(&NYQUIST), FilterMode::LowPass, cutoff, 707);
+        assert!(peak(svf) < 80);
+    }
+
+    #[test]
+    fn first_sample() {
+        let identity = Coefs {
+            b0: ONE,
+            ..Coefs::default()
+        };
+        let mut osc = input(&NYQUIST);
+        let mut biquad = Biquad::with_coefs(input(&NYQUIST), identity);
+        assert_eq!(biquad.step(), 0);
+        for _ in 0..16 {
+            assert_eq!(biquad.step(), osc.step());
+        }
+    }
+
+    #[test]
+    fn extreme_coefs() {
+        let cutoff = Freq::from_hz(1000);
+        let coefs = Coefs::new(FilterMode::BandPass, cutoff, 0, RATE);
+        assert_eq!(coefs, Coefs::new(FilterMode::BandPass, cutoff, 500, RATE));
+        let coefs = Coefs::peak(cutoff, 1, i16::MAX, RATE);
+        assert_eq!(coefs, Coefs::peak(cutoff, 500, 2400, RATE));
+        assert!(coefs.b0 > 0);
+    }
+}
@@ -0,0 +1,378 @@
+use crate::oscillator::sin;
+use crate::{Adsr, Freq, Sound, MAX_VOL, RATE};
+
+// FM (in fact phase modulation, as on the DX7 and the OPL/OPN chips)
+// voices of 2 or 4 operators. Each operator is a sine with its own
+// envelope, at a fixed ratio of the frequency of the note. The outputs
+// of the modulators are added to the phase of the operators they
+// modulate: a modulator at full volume shifts the phase by a whole
+// period. The first operator can modulate itself.
+
+#[derive(Clone, Debug)]
+pub struct Operator {
+    rate: u32,
+    vol: i16,
+    freq: Freq,
+    // frequency ratio to the note
+    num: u32,
+    den: u32,
+    playing: bool,
+    // 2^32 is a full period
+    phase: u32,
+    inc: u32,
+}
+impl Operator {
+    pub fn new(num: u32, den: u32) -> Self {
+        Self::with_rate(num, den, RATE)
+    }
+    pub fn with_rate(num: u32, den: u32, rate: u32) -> Self {
+        Operator {
+            rate,
+            vol: MAX_VOL,
+            freq: Freq(0),
+            num,
+            den,
+            playing: false,
+            phase: 0,
+            inc: 0,
+        }
+    }
+    pub fn freq(&self) -> Freq {
+        self.freq
+    }
+    // the output with the phase shifted by `modulation`
+    pub fn output(&self, modulation: i32) -> i16 {
+        if !self.playing {
+            return 0;
+        }
+        let phase = self.phase.wrapping_add(((modulation as i64) << 17) as u32);
+        ((sin(phase) >> 15) * self.vol as i32 / MAX_VOL as i32) as i16
+    }
+}
+
+impl Sound for Operator {
+    fn vol(&self) -> i16 {
+        self.vol
+    }
+    fn rate(&self) -> u32 {
+        self.rate
+    }
+    fn get(&self) -> i16 {
+        self.output(0)
+    }
+    fn advance(&mut self) {
+        self.phase = self.phase.wrapping_add(self.inc);
+    }
+    // restarts the period, so that the modulations sound the same at
+    // each note
+    fn set_freq(&mut self, freq: Freq) {
+        self.phase = 0;
+        self.playing = true;
+        self.bend(freq);
+    }
+    fn bend(&mut self, freq: Freq) {
+        self.freq = freq;
+        let mhz = freq.mul_ratio(self.num, self.den).mhz() as u64;
+        self.inc = ((mhz << 32) / (self.rate as u64 * 1000)) as u32;
+    }
+    fn sync(&mut self) {
+        self.phase = 0;
+    }
+    fn set_phase(&mut self, phase: u32) {
+        self.phase = phase;
+    }
+    fn stop(&mut self) {
+        self.playing = false;
+    }
+    fn set_vol(&mut self, vol: i16) {
+        self.vol = vol;
+    }
+}
+
+// The operators are computed in order. `mods[i]` is the mask of the
+// operators modulating the operator i, and `carriers` the mask of the
+// operators heard.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Algorithm {
+    pub mods: [u8; 4],
+    pub carriers: u8,
+}
+
+// 1 → 2
+pub const FM: Algorithm = Algorithm {
+    mods: [0, 0b1, 0, 0],
+    carriers: 0b10,
+};
+// 1 + 2
+pub const ADDITIVE: Algorithm = Algorithm {
+    mods: [0; 4],
+    carriers: 0b11,
+};
+// the 8 algorithms of the 4 operators OPN chips
+pub const ALGORITHMS: [Algorithm; 8] = [
+    // 1 → 2 → 3 → 4
+    Algorithm {
+        mods: [0, 0b1, 0b10, 0b100],
+        carriers: 0b1000,
+    },
+    // (1 + 2) → 3 → 4
+    Algorithm {
+        mods: [0, 0, 0b11, 0b100],
+        carriers: 0b1000,
+    },
+    // (1 + (2 → 3)) → 4
+    Algorithm {
+        mods: [0, 0, 0b10, 0b101],
+        carriers: 0b1000,
+    },
+    // ((1 → 2) + 3) → 4
+    Algorithm {
+        mods: [0, 0b1, 0, 0b110],
+        carriers: 0b1000,
+    },
+    // (1 → 2) + (3 → 4)
+    Algorithm {
+        mods: [0, 0b1, 0, 0b100],
+        carriers: 0b1010,
+    },
+    // 1 → (2 + 3 + 4)
+    Algorithm {
+        mods: [0, 0b1, 0b1, 0b1],
+        carriers: 0b1110,
+    },
+    // (1 → 2) + 3 + 4
+    Algorithm {
+        mods: [0, 0b1, 0, 0],
+        carriers: 0b1110,
+    },
+    // 1 + 2 + 3 + 4
+    Algorithm {
+        mods: [0; 4],
+        carriers: 0b1111,
+    },
+];
+
+#[derive(Clone, Debug)]
+pub struct FmVoice<const N: usize> {
+    ops: [Adsr<Operator>; N],
+    pub algorithm: Algorithm,
+    // from 0 (none) to 7
+    pub feedback: u8,
+    vol: i16,
+    // the last two outputs of the first operator
+    last: [i16; 2],
+    out: i16,
+}
+impl<const N: usize> FmVoice<N> {
+    // fails to build for other numbers of operators than 2 and 4
+    const OPERATORS: () = assert!(N == 2 || N == 4, "an FM voice has 2 or 4 operators");
+    pub fn new(ops: [Adsr<Operator>; N], algorithm: Algorithm, feedback: u8) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::OPERATORS;
+        FmVoice {
+            ops,
+            algorithm,
+            feedback,
+            vol: MAX_VOL,
+            last: [0; 2],
+            out: 0,
+        }
+    }
+    pub fn ops_mut(&mut self) -> &mut [Adsr<Operator>; N] {
+        &mut self.ops
+    }
+    fn compute(&mut self) -> i16 {
+        let mut outputs = [0i16; 4];
+        let (mut sum, mut carriers) = (0i32, 0i32);
+        for (i, op) in self.ops.iter().enumerate() {
+            let mask = self.algorithm.mods[i];
+            let mut modulation: i32 = (0..i)
+                .filter(|j| mask & 1 << j != 0)
+                .map(|j| outputs[j] as i32)
+                .sum();
+            if i == 0 && self.feedback > 0 {
+                let last = (self.last[0] as i32 + self.last[1] as i32) / 2;
+                modulation += last >> (8 - self.feedback.min(7));
+            }
+            outputs[i] = op.sound().output(modulation);
+            if self.algorithm.carriers & 1 << i != 0 {
+                sum += outputs[i] as i32;
+                carriers += 1;
+            }
+        }
+        self.last = [outputs[0], self.last[0]];
+        (sum / carriers.max(1) * self.vol as i32 / MAX_VOL as i32) as i16
+    }
+}
+
+impl<const N: usize> Sound for FmVoice<N> {
+    fn vol(&self) -> i16 {
+        self.vol
+    }
+    fn level(&self) -> i16 {
+        let carriers = self.algorithm.carriers;
+        self.ops
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| carriers & 1 << i != 0)
+            .map(|(_, op)| op.level())
+            .max()
+            .unwrap_or(0)
+    }
+    fn rate(&self) -> u32 {
+        self.ops.first().map_or(RATE, |op| op.rate())
+    }
+    fn get(&self) -> i16 {
+        self.out
+    }
+    fn advance(&mut self) {
+        for op in self.ops.iter_mut() {
+            op.advance();
+        }
+        self.out = self.compute();
+    }
+    fn set_freq(&mut self, freq: Freq) {
+        for op in self.ops.iter_mut() {
+            op.set_freq(freq);
+        }
+    }
+    fn bend(&mut self, freq: Freq) {
+        for op in self.ops.iter_mut() {
+            op.bend(freq);
+        }
+    }
+    fn sync(&mut self) {
+        for op in self.ops.iter_mut() {
+            op.sync();
+        }
+    }
+    fn set_phase(&mut self, phase: u32) {
+        for op in self.ops.iter_mut() {
+            op.set_phase(phase);
+        }
+    }
+    fn stop(&mut self) {
+        for op in self.ops.iter_mut() {
+            op.stop();
+        }
+    }
+    fn set_vol(&mut self, vol: i16) {
+        self.vol = vol;
+    }
+}
+
+fn op(num: u32, den: u32, vol: i16, adsr: (u32, u32, i16, u32)) -> Adsr<Operator> {
+    let (attack_ms, decay_ms, sustain, release_ms) = adsr;
+    let mut res = Adsr::new(
+        Operator::new(num, den),
+        attack_ms,
+        decay_ms,
+        sustain,
+        release_ms,
+    );
+    res.set_vol(vol);
+    res
+}
+
+// an inharmonic modulator with a long decay
+pub fn bell() -> FmVoice<2> {
+    FmVoice::new(
+        [
+            op(7, 2, MAX_VOL / 2, (1, 2000, 0, 2000)),
+            op(1, 1, MAX_VOL, (1, 4000, 0, 4000)),
+        ],
+        FM,
+        0,
+    )
+}
+
+// a tine and a body, as the DX7 electric piano
+pub fn electric_piano() -> FmVoice<4> {
+    FmVoice::new(
+        [
+            op(14, 1, MAX_VOL / 8, (1, 300, 0, 100)),
+            op(1, 1, MAX_VOL, (1, 3000, 0, 300)),
+            op(1, 1, MAX_VOL / 5, (1, 1500, MAX_VOL / 4, 300)),
+            op(1, 1, MAX_VOL, (1, 4000, 0, 300)),
+        ],
+        ALGORITHMS[4],
+        0,
+    )
+}
+
+pub fn bass() -> FmVoice<2> {
+    FmVoice::new(
+        [
+            op(1, 1, MAX_VOL / 3, (1, 200, MAX_VOL / 4, 50)),
+            op(1, 1, MAX_VOL, (1, 1000, MAX_VOL / 2, 50)),
+        ],
+        FM,
+        5,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pitch::A4;
+
+    fn ops(vols: [i16; 4]) -> [Adsr<Operator>; 4] {
+        let mut ratio = 0;
+        vols.map(|vol| {
+            ratio += 1;
+            op(ratio, 1, vol, (1, 100, MAX_VOL, 100))
+        })
+    }
+    // whether the voice plays the mix of its carriers without any
+    // modulation
+    fn unmodulated(mut voice: FmVoice<4>, vols: [i16; 4]) -> bool {
+        let mut ops = ops(vols);
+        let carriers = voice.algorithm.carriers;
+        voice.set_freq(A4);
+        for op in ops.iter_mut() {
+            op.set_freq(A4);
+        }
+        (0..RATE / 10).all(|_| {
+            voice.advance();
+            let (mut sum, mut n) = (0, 0);
+            for (i, op) in ops.iter_mut().enumerate() {
+                op.advance();
+                if carriers & 1 << i != 0 {
+                    sum += op.get() as i32;
+                    n += 1;
+                }
+            }
+            voice.get() as i32 == sum / n
+        })
+    }
+
+    #[test]
+    fn algorithms() {
+        for &algorithm in ALGORITHMS.iter().chain(&[FM, ADDITIVE]) {
+            // the carriers at different volumes
+            let carriers = [1, 2, 3, 4].map(|i| match algorithm.carriers & 1 << (i - 1) {
+                0 => 0,
+                _ => MAX_VOL / i,
+            });
+            let voice = FmVoice::new(ops(carriers), algorithm, 0);
+            assert!(unmodulated(voice, carriers));
+            let modulated = algorithm.mods.iter().any(|&m| m != 0);
+            let voice = FmVoice::new(ops([MAX_VOL / 2; 4]), algorithm, 0);
+            assert_eq!(unmodulated(voice, [MAX_VOL / 2; 4]), !modulated);
+        }
+    }
+
+    #[test]
+    fn feedback() {
+        let alone = Algorithm {
+            mods: [0; 4],
+            carriers: 0b1,
+        };
+        let vols = [MAX_VOL, 0, 0, 0];
+        assert!(unmodulated(FmVoice::new(ops(vols), alone, 0), vols));
+        for feedback in 1..8 {
+            let voice = FmVoice::new(ops(vols), alone, feedback);
+            assert!(!unmodulated(voice, vols));
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use crate::modulation::CONTROL_TICKS;
+use crate::pitch::millicents;
+use crate::{Curve, Freq, Sound, MAX_VOL};
+
+// Portamento: a new note slides from the pitch of the previous one
+// over `ms`, with the shape of the curve in cents, so that a linear
+// slide moves at a steady musical speed. In legato mode, the slide
+// only happens when the previous note is still held, and then the new
+// note is not retriggered, so an `Adsr` keeps its sustain. In a score,
+// the notes held for 100% of their duration are played legato.
+
+#[derive(Clone, Debug)]
+pub struct Glide<S> {
+    sound: S,
+    pub ms: u32,
+    pub legato: bool,
+    pub curve: Curve,
+    held: bool,
+    from: Freq,
+    to: Freq,
+    // from `from` to `to`, in thousandths of a cent
+    span: i32,
+    // remaining samples of the slide, out of total
+    ticks: u32,
+    total: u32,
+}
+impl<S: Sound> Glide<S> {
+    pub fn new(sound: S, ms: u32, legato: bool) -> Self {
+        Glide {
+            sound,
+            ms,
+            legato,
+            curve: Curve::Linear,
+            held: false,
+            from: Freq(0),
+            to: Freq(0),
+            span: 0,
+            ticks: 0,
+            total: 0,
+        }
+    }
+    pub fn with_curve(mut self, curve: Curve) -> Self {
+        self.curve = curve;
+        self
+    }
+    pub fn sound(&self) -> &S {
+        &self.sound
+    }
+    // the current pitch of the slide
+    pub fn freq(&self) -> Freq {
+        if self.ticks == 0 {
+            return self.to;
+        }
+        let done = self.curve.ratio(0, MAX_VOL, self.ticks, self.total) as i64;
+        let millicents = self.span as i64 * done / MAX_VOL as i64;
+        self.from.add_millicents(millicents as i32)
+    }
+}
+
+impl<S: Sound> Sound for Glide<S> {
+    fn vol(&self) -> i16 {
+        self.sound.vol()
+    }
+    fn level(&self) -> i16 {
+        self.sound.level()
+    }
+    fn rate(&self) -> u32 {
+        self.sound.rate()
+    }
+    fn get(&self) -> i16 {
+        self.sound.get()
+    }
+    fn advance(&mut self) {
+        self.sound.advance();
+        if self.ticks == 0 {
+            return;
+        }
+        self.ticks -= 1;
+        if self.ticks % CONTROL_TICKS == 0 {
+            self.sound.bend(self.freq());
+        }
+    }
+    fn set_freq(&mut self, freq: Freq) {
+        let last = self.freq();
+        let slide = self.ms > 0 && last != Freq(0) && (self.held || !self.legato);
+        let retrigger = !(self.held && self.legato);
+        self.held = true;
+        self.to = freq;
+        if !slide {
+            self.from = freq;
+            self.ticks = 0;
+            if retrigger {
+                self.sound.set_freq(freq);
+            } else {
+                self.sound.bend(freq);
+            }
+            return;
+        }
+        self.from = last;
+        self.span = millicents(last, freq);
+        self.total = (self.ms as u64 * self.rate() as u64 / 1000) as u32;
+        self.ticks = self.total;
+        if retrigger {
+            self.sound.set_freq(last);
+        }
+    }
+    // moves at once to the frequency, ending the slide
+    fn bend(&mut self, freq: Freq) {
+        self.from = freq;
+        self.to = freq;
+        self.ticks = 0;
+        self.sound.bend(freq);
+    }
+    fn sync(&mut self) {
+        self.sound.sync();
+    }
+    fn set_phase(&mut self, phase: u32) {
+        self.sound.set_phase(phase);
+    }
+    fn stop(&mut self) {
+        self.held = false;
+        self.sound.stop();
+    }
+    fn set_vol(&mut self, vol: i16) {
+        self.sound.set_vol(vol);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pitch::{A4, A5};
+    use crate::{Adsr, Oscillator};
+
+    fn glide(ms: u32, legato: bool) -> Glide<Adsr<Oscillator>> {
+        Glide::new(
+            Adsr::new(Oscillator::default(), 10, 10, MAX_VOL / 2, 10),
+            ms,
+            legato,
+        )
+    }
+
+    #[test]
+    fn linear_in_pitch() {
+        let mut glide = glide(100, false);
+        glide.set_freq(A4);
+        glide.set_freq(A5);
+        for _ in 0..crate::RATE / 20 {
+            glide.step();
+        }
+        // half an octave
+        assert!((glide.freq().mhz() as i32 - 622_254).abs() < 100);
+    }
+
+    #[test]
+    fn legato_without_glide() {
+        let mut glide = glide(0, true);
+        glide.set_freq(A4);
+        for _ in 0..crate::RATE / 10 {
+            glide.step();
+        }
+        glide.set_freq(A5);
+        glide.step();
+        assert_eq!(glide.level(), MAX_VOL / 2);
+        assert_eq!(glide.sound().sound().freq(), A5);
+    }
+}
@@ -1,24 +1,67 @@
 #![no_std]
+// `is_multiple_of` needs Rust 1.87
+#![allow(clippy::manual_is_multiple_of)]
 
 mod adsr;
+mod band_limited;
+mod combine;
+pub mod drums;
+mod envelope;
+mod filter;
+pub mod fm;
+mod glide;
+pub mod midi;
+pub mod mml;
+mod modulation;
+mod noise;
 mod oscillator;
 pub mod pitch;
+mod pluck;
+mod polyphony;
+pub mod rtttl;
+pub mod sampler;
 pub mod songs;
+mod unison;
+mod wavetable;
 
-pub use adsr::Adsr;
+pub use adsr::{Adsr, Curve};
+pub use band_limited::{BandLimited, Pulse, Waveform, DUTY_12_5, DUTY_25, DUTY_50};
+pub use combine::{HardSync, RingMod, RingMode};
+pub use drums::Kit;
+pub use envelope::{Env, Envelope, Stage};
+pub use filter::{Biquad, Coefs, FilterMode, Svf};
+pub use fm::{FmVoice, Operator};
+pub use glide::Glide;
+pub use modulation::{Lfo, LfoShape, Modulated, Modulator, Route, Source, Target};
+pub use noise::{Noise, NoiseColor};
 pub use oscillator::Oscillator;
+pub use pitch::Freq;
+pub use pluck::Pluck;
+pub use polyphony::{Polyphony, Stealing};
+pub use sampler::Sampler;
+pub use unison::Unison;
+pub use wavetable::Wavetable;
 
 pub const RATE: u32 = 48000;
+#[allow(clippy::legacy_numeric_constants)]
 pub const MAX_VOL: i16 = core::i16::MAX;
 
 pub enum Action {
     Vol(i16),
-    Start(u16),
+    Start(Freq),
     Stop,
 }
 
 pub trait Sound {
     fn vol(&self) -> i16;
+    // the current loudness, that may differ from `vol` when the
+    // volume is driven by an envelope
+    fn level(&self) -> i16 {
+        self.vol()
+    }
+    // the sample rate, in Hz; required, so that a wrapper never
+    // silently runs at the default rate instead of its sound's one
+    fn rate(&self) -> u32;
     fn get(&self) -> i16;
     fn advance(&mut self);
     fn step(&mut self) -> i16 {
@@ -27,7 +70,21 @@ pub trait Sound {
         res
     }
 
-    fn set_freq(&mut self, freq: u16);
+    fn set_freq(&mut self, freq: Freq);
+    fn set_freq_hz(&mut self, freq: u16) {
+        self.set_freq(Freq::from_hz(freq));
+    }
+    // changes the frequency of the current note without retriggering
+    // it, for vibratos and glides
+    fn bend(&mut self, freq: Freq) {
+        self.set_freq(freq);
+    }
+    // restarts the period of the waveform without retriggering the
+    // note, for hard sync
+    fn sync(&mut self) {}
+    // moves to a point of the period, 2^32 being a full one, without
+    // retriggering the note
+    fn set_phase(&mut self, _phase: u32) {}
     fn stop(&mut self);
     fn set_vol(&mut self, vol: i16);
 
@@ -43,50 +100,74 @@ pub trait Sound {
     where
         Self: Sized,
     {
-        let mut events = score.events();
-        Player {
-            sound: self,
-            t: score.ms_duration() * RATE / 1000,
-            event: events.next(),
-            events,
-            next_ms: 0,
-        }
+        Player::new(self, score.events())
     }
 }
 
 #[derive(Debug, Clone)]
-pub struct Player<S> {
+pub struct Player<S, E = songs::Events> {
     sound: S,
     event: Option<songs::Event>,
-    events: songs::Events,
+    events: E,
+    // the volume of the sound, the volumes of the events are relative
+    // to it
+    vol: i16,
+    // remaining samples
     t: u32,
-    next_ms: u32,
+    // current sample, and sample of the next event
+    pos: u32,
+    next_pos: u32,
+    // start of the next event, in us since the beginning
+    next_us: u64,
 }
-impl<S: Sound> Player<S> {
+impl<S: Sound, E: Iterator<Item = songs::Event> + Clone> Player<S, E> {
+    pub fn new(sound: S, mut events: E) -> Self {
+        let us_duration: u64 = events.clone().map(|e| e.us_duration() as u64).sum();
+        Player {
+            vol: sound.vol(),
+            t: us_to_samples(us_duration, sound.rate()),
+            sound,
+            event: events.next(),
+            events,
+            pos: 0,
+            next_pos: 0,
+            next_us: 0,
+        }
+    }
+}
+impl<S: Sound, E> Player<S, E> {
     pub fn into_sound(self) -> S {
         self.sound
     }
 }
-impl<S: Sound> Iterator for Player<S> {
+// Events are scheduled from their exact start since the beginning, so
+// rounding to the sample never accumulates.
+fn us_to_samples(us: u64, rate: u32) -> u32 {
+    ((us * rate as u64 + 500_000) / 1_000_000) as u32
+}
+impl<S: Sound, E: Iterator<Item = songs::Event>> Iterator for Player<S, E> {
     type Item = i16;
     fn next(&mut self) -> Option<Self::Item> {
         if self.t == 0 {
             return None;
         }
-        if self.t % (RATE / 1000) == 0 {
-            loop {
-                match self.event {
-                    None => break,
-                    Some(_) if self.next_ms != 0 => break,
-                    Some(e) => {
-                        self.sound.modify(&e.to_action());
-                        self.next_ms = e.ms_duration();
-                    }
+        while self.next_pos <= self.pos {
+            let e = match self.event {
+                None => break,
+                Some(e) => e,
+            };
+            match e {
+                songs::Event::Vol { vol } => {
+                    let vol = (self.vol as i32 * vol as i32 / MAX_VOL as i32) as i16;
+                    self.sound.set_vol(vol);
                 }
-                self.event = self.events.next();
+                e => self.sound.modify(&e.to_action()),
             }
-            self.next_ms -= 1;
+            self.next_us += e.us_duration() as u64;
+            self.next_pos = us_to_samples(self.next_us, self.sound.rate());
+            self.event = self.events.next();
         }
+        self.pos += 1;
         self.t -= 1;
         Some(self.sound.step())
     }
@@ -94,7 +175,67 @@ impl<S: Sound> Iterator for Player<S> {
         (self.t as usize, Some(self.t as usize))
     }
 }
-impl<S: Sound> core::iter::ExactSizeIterator for Player<S> {}
+impl<S: Sound, E: Iterator<Item = songs::Event>> core::iter::ExactSizeIterator for Player<S, E> {}
+
+// Plays up to N tracks in sync, each one with its own sound, and mixes
+// them into a single sample stream. Tracks beyond N are ignored, as
+// the tracks of an instrument missing from the instruments.
+#[derive(Debug, Clone)]
+pub struct SongPlayer<S, E, const N: usize> {
+    players: [Option<Player<S, E>>; N],
+    t: u32,
+}
+impl<S: Sound + Clone, const N: usize> SongPlayer<S, songs::Events, N> {
+    pub fn from_song(instruments: &[S], song: &songs::Song) -> Self {
+        Self::new(song.tracks().filter_map(|(instrument, events)| {
+            Some((instruments.get(instrument)?.clone(), events))
+        }))
+    }
+    pub fn from_score(instrument: S, score: &songs::Score) -> Self {
+        Self::new(Some((instrument, score.events())))
+    }
+}
+impl<S: Sound, E: Iterator<Item = songs::Event> + Clone, const N: usize> SongPlayer<S, E, N> {
+    pub fn new<I: IntoIterator<Item = (S, E)>>(tracks: I) -> Self {
+        let mut tracks = tracks.into_iter();
+        let players: [Option<Player<S, E>>; N] = core::array::from_fn(|_| {
+            tracks
+                .next()
+                .map(|(sound, events)| Player::new(sound, events))
+        });
+        let t = players.iter().flatten().map(|p| p.t).max().unwrap_or(0);
+        Self { players, t }
+    }
+}
+impl<S: Sound, E, const N: usize> SongPlayer<S, E, N> {
+    pub fn into_sounds(self) -> [Option<S>; N] {
+        let mut players = self.players;
+        core::array::from_fn(|i| players[i].take().map(Player::into_sound))
+    }
+}
+impl<S: Sound, E: Iterator<Item = songs::Event>, const N: usize> Iterator for SongPlayer<S, E, N> {
+    type Item = i16;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.t == 0 {
+            return None;
+        }
+        self.t -= 1;
+        let res = self
+            .players
+            .iter_mut()
+            .flatten()
+            .filter_map(|p| p.next())
+            .fold(0i16, |acc, s| acc.saturating_add(s));
+        Some(res)
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.t as usize, Some(self.t as usize))
+    }
+}
+impl<S: Sound, E: Iterator<Item = songs::Event>, const N: usize> core::iter::ExactSizeIterator
+    for SongPlayer<S, E, N>
+{
+}
 
 pub fn mix<I1, I2>(iter1: I1, iter2: I2) -> Mix<I1, I2> {
     Mix { iter1, iter2 }
@@ -118,6 +259,14 @@ where
     }
 }
 
+// the next state of a xorshift32 generator, never 0 from a non zero seed
+pub(crate) fn xorshift32(seed: &mut u32) -> u32 {
+    *seed ^= *seed << 13;
+    *seed ^= *seed >> 17;
+    *seed ^= *seed << 5;
+    *seed
+}
+
 pub(crate) fn compute_ratio(from: i16, to: i16, num: u32, denom: u32) -> i16 {
     if denom == 0 {
         return from;
@@ -131,3 +280,75 @@ pub(crate) fn compute_ratio(from: i16, to: i16, num: u32, denom: u32) -> i16 {
 
     ((to as i32 - from as i32) * num / denom + from as i32) as i16
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use songs::{Mark, Song, Track};
+
+    // counts the notes, and records the sample at which the last one
+    // started
+    #[derive(Clone, Debug, Default)]
+    struct Probe {
+        pos: u32,
+        notes: u32,
+        start: u32,
+    }
+    impl Sound for Probe {
+        fn vol(&self) -> i16 {
+            MAX_VOL
+        }
+        fn rate(&self) -> u32 {
+            22050
+        }
+        fn get(&self) -> i16 {
+            0
+        }
+        fn advance(&mut self) {
+            self.pos += 1;
+        }
+        fn set_freq(&mut self, _freq: Freq) {
+            self.notes += 1;
+            self.start = self.pos;
+        }
+        fn stop(&mut self) {}
+        fn set_vol(&mut self, _vol: i16) {}
+    }
+
+    #[test]
+    fn no_drift() {
+        // 459.37 samples per note
+        let note = songs::Event::Note {
+            pitch: pitch::A4,
+            us: 20_833,
+        };
+        let mut player = Player::new(Probe::default(), (0..10_000).map(|_| note));
+        assert_eq!(player.len(), 4_593_677);
+        player.by_ref().for_each(drop);
+        let probe = player.into_sound();
+        assert_eq!(probe.notes, 10_000);
+        // 9999 × 20833 us, rounded to the nearest sample
+        assert_eq!(probe.start, 4_593_217);
+    }
+
+    #[test]
+    fn missing_instrument() {
+        static NOTES: [Mark; 1] = [Mark::Note(pitch::A4, 1, 4, 100)];
+        static TRACKS: [Track; 2] = [
+            Track {
+                instrument: 3,
+                notes: &NOTES,
+            },
+            Track {
+                instrument: 0,
+                notes: &NOTES,
+            },
+        ];
+        let song = Song {
+            tempo: 60,
+            tracks: &TRACKS,
+        };
+        let player = SongPlayer::<_, _, 2>::from_song(&[Oscillator::default()], &song);
+        assert_eq!(player.count(), RATE as usize / 4);
+    }
+}
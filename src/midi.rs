@@ -0,0 +1,413 @@
+use crate::songs::{ms_duration, Event};
+use crate::{pitch, MAX_VOL};
+
+// Standard MIDI File (type 0 and 1) reader. Each track is played
+// monophonically: a note on cuts the previous note of the track.
+// The velocities of the notes are given as volume events.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    NotSmf,
+    UnsupportedFormat(u16),
+    Truncated { offset: usize },
+    BadEvent { offset: usize },
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Smf<'a> {
+    pub format: u16,
+    data: &'a [u8],
+    division: u16,
+    chunks: usize,
+}
+impl<'a> Smf<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self, Error> {
+        if data.len() < 14 || &data[..4] != b"MThd" || read_u32(&data[4..]) < 6 {
+            return Err(Error::NotSmf);
+        }
+        let format = read_u16(&data[8..]);
+        if format > 1 {
+            return Err(Error::UnsupportedFormat(format));
+        }
+        let chunks = (read_u32(&data[4..]) as usize)
+            .checked_add(8)
+            .ok_or(Error::Truncated { offset: data.len() })?;
+        let smf = Smf {
+            format,
+            data,
+            division: read_u16(&data[12..]),
+            chunks,
+        };
+        for track in smf.raw_tracks() {
+            let mut reader = track?;
+            while reader.next_msg()?.is_some() {}
+        }
+        Ok(smf)
+    }
+    pub fn nb_tracks(&self) -> usize {
+        self.raw_tracks().count()
+    }
+    pub fn track(&self, i: usize) -> Option<TrackEvents<'a>> {
+        self.tracks().nth(i)
+    }
+    pub fn tracks(&self) -> impl Iterator<Item = TrackEvents<'a>> + 'a {
+        let smf = *self;
+        // the tempo map is in the first track
+        let tempo = self.raw_tracks().next().and_then(Result::ok);
+        self.raw_tracks().filter_map(Result::ok).map(move |reader| {
+            let tempo = tempo.clone().unwrap_or_else(|| reader.clone());
+            TrackEvents {
+                reader,
+                tempo: TempoMap::new(tempo, smf.division),
+                key: None,
+                vel: 127,
+                note: None,
+                start_us: 0,
+                done: false,
+            }
+        })
+    }
+    pub fn ms_duration(&self) -> u32 {
+        self.tracks().map(ms_duration).max().unwrap_or(0)
+    }
+    fn raw_tracks(&self) -> impl Iterator<Item = Result<Reader<'a>, Error>> + 'a {
+        let data = self.data;
+        let mut offset = self.chunks;
+        core::iter::from_fn(move || loop {
+            if offset >= data.len() {
+                return None;
+            }
+            if data.len() < offset + 8 {
+                offset = data.len();
+                return Some(Err(Error::Truncated { offset }));
+            }
+            let start = offset + 8;
+            let end = match start.checked_add(read_u32(&data[offset + 4..]) as usize) {
+                Some(end) if end <= data.len() => end,
+                _ => {
+                    offset = data.len();
+                    return Some(Err(Error::Truncated { offset }));
+                }
+            };
+            let is_track = &data[offset..offset + 4] == b"MTrk";
+            offset = end;
+            if is_track {
+                return Some(Ok(Reader {
+                    data: &data[..end],
+                    pos: start,
+                    status: 0,
+                    tick: 0,
+                }));
+            }
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TrackEvents<'a> {
+    reader: Reader<'a>,
+    tempo: TempoMap<'a>,
+    // current note and its velocity
+    key: Option<(u8, u8)>,
+    vel: u8,
+    note: Option<Event>,
+    start_us: u64,
+    done: bool,
+}
+impl<'a> Iterator for TrackEvents<'a> {
+    type Item = Event;
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(note) = self.note.take() {
+            return Some(note);
+        }
+        while !self.done {
+            let (tick, key) = match self.reader.next_msg() {
+                Ok(Some((tick, Msg::NoteOn(key, vel)))) => (tick, Some((key, vel))),
+                Ok(Some((tick, Msg::NoteOff(key)))) if self.key.map(|k| k.0) == Some(key) => {
+                    (tick, None)
+                }
+                Ok(Some(_)) => continue,
+                Ok(None) | Err(_) => {
+                    self.done = true;
+                    (self.reader.tick, None)
+                }
+            };
+            let us = self.tempo.us_at(tick);
+            let dur = (us - self.start_us) as u32;
+            let (event, vel) = match self.key {
+                Some((key, vel)) => {
+                    let pitch = pitch::midi(key);
+                    (Event::Note { pitch, us: dur }, vel)
+                }
+                None => (Event::Rest { us: dur }, self.vel),
+            };
+            self.key = key;
+            self.start_us = us;
+            if dur == 0 {
+                continue;
+            }
+            if vel == self.vel {
+                return Some(event);
+            }
+            self.vel = vel;
+            self.note = Some(event);
+            let vol = (MAX_VOL as i32 * vel as i32 / 127) as i16;
+            return Some(Event::Vol { vol });
+        }
+        None
+    }
+}
+
+#[derive(Clone, Debug)]
+struct TempoMap<'a> {
+    reader: Reader<'a>,
+    division: u16,
+    next: Option<(u32, u32)>,
+    tick: u32,
+    us: u64,
+    tempo: u32,
+}
+impl<'a> TempoMap<'a> {
+    fn new(reader: Reader<'a>, division: u16) -> Self {
+        let mut res = TempoMap {
+            reader,
+            division,
+            next: None,
+            tick: 0,
+            us: 0,
+            tempo: 500_000,
+        };
+        res.next = res.next_tempo();
+        res
+    }
+    fn next_tempo(&mut self) -> Option<(u32, u32)> {
+        loop {
+            match self.reader.next_msg() {
+                Ok(Some((tick, Msg::Tempo(tempo)))) => return Some((tick, tempo)),
+                Ok(Some(_)) => {}
+                Ok(None) | Err(_) => return None,
+            }
+        }
+    }
+    fn ticks_to_us(&self, ticks: u32) -> u64 {
+        if self.division & 0x8000 != 0 {
+            // SMPTE: negative frames per second and ticks per frame
+            let fps = ((self.division >> 8) as u8 as i8).unsigned_abs() as u64;
+            let tpf = (self.division & 0xff) as u64;
+            ticks as u64 * 1_000_000 / (fps * tpf).max(1)
+        } else {
+            ticks as u64 * self.tempo as u64 / (self.division as u64).max(1)
+        }
+    }
+    fn us_at(&mut self, tick: u32) -> u64 {
+        while let Some((t, tempo)) = self.next {
+            if t > tick {
+                break;
+            }
+            self.us += self.ticks_to_us(t - self.tick);
+            self.tick = t;
+            self.tempo = tempo;
+            self.next = self.next_tempo();
+        }
+        self.us + self.ticks_to_us(tick - self.tick)
+    }
+}
+
+enum Msg {
+    NoteOn(u8, u8),
+    NoteOff(u8),
+    Tempo(u32),
+    Other,
+}
+
+#[derive(Clone, Debug)]
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    status: u8,
+    tick: u32,
+}
+impl<'a> Reader<'a> {
+    fn read_u8(&mut self) -> Result<u8, Error> {
+        let res = *self
+            .data
+            .get(self.pos)
+            .ok_or(Error::Truncated { offset: self.pos })?;
+        self.pos += 1;
+        Ok(res)
+    }
+    fn read_var(&mut self) -> Result<u32, Error> {
+        let mut res = 0;
+        for _ in 0..4 {
+            let b = self.read_u8()?;
+            res = res << 7 | (b & 0x7f) as u32;
+            if b & 0x80 == 0 {
+                return Ok(res);
+            }
+        }
+        Err(Error::BadEvent { offset: self.pos })
+    }
+    fn skip(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let res = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or(Error::Truncated {
+                offset: self.data.len(),
+            })?;
+        self.pos += len;
+        Ok(res)
+    }
+    fn next_msg(&mut self) -> Result<Option<(u32, Msg)>, Error> {
+        if self.pos >= self.data.len() {
+            return Ok(None);
+        }
+        self.tick = self.tick.saturating_add(self.read_var()?);
+        let offset = self.pos;
+        let mut first = self.read_u8()?;
+        if first & 0x80 != 0 {
+            self.status = first;
+            if first < 0xf0 {
+                first = self.read_u8()?;
+            }
+        } else if self.status == 0 {
+            return Err(Error::BadEvent { offset });
+        }
+        let msg = match self.status {
+            0x80..=0x8f => {
+                self.read_u8()?;
+                Msg::NoteOff(first)
+            }
+            0x90..=0x9f => match self.read_u8()? {
+                0 => Msg::NoteOff(first),
+                vel => Msg::NoteOn(first, vel),
+            },
+            0xa0..=0xbf | 0xe0..=0xef => {
+                self.read_u8()?;
+                Msg::Other
+            }
+            0xc0..=0xdf => Msg::Other,
+            0xf0 | 0xf7 => {
+                self.status = 0;
+                let len = self.read_var()? as usize;
+                self.skip(len)?;
+                Msg::Other
+            }
+            0xff => {
+                self.status = 0;
+                let kind = self.read_u8()?;
+                let len = self.read_var()? as usize;
+                let data = self.skip(len)?;
+                match (kind, data) {
+                    (0x2f, _) => {
+                        self.pos = self.data.len();
+                        return Ok(None);
+                    }
+                    (0x51, &[a, b, c]) => Msg::Tempo((a as u32) << 16 | (b as u32) << 8 | c as u32),
+                    _ => Msg::Other,
+                }
+            }
+            _ => return Err(Error::BadEvent { offset }),
+        };
+        Ok(Some((self.tick, msg)))
+    }
+}
+
+fn read_u16(data: &[u8]) -> u16 {
+    (data[0] as u16) << 8 | data[1] as u16
+}
+fn read_u32(data: &[u8]) -> u32 {
+    (read_u16(data) as u32) << 16 | read_u16(&data[2..]) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pitch::{A4, C5};
+
+    // one track at 96 ticks per quarter note, with the default tempo
+    // of 120 quarters per minute set explicitly
+    static SMF: [u8; 48] = [
+        b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 0, 0, 1, 0, 96, //
+        b'M', b'T', b'r', b'k', 0, 0, 0, 26, //
+        0, 0xff, 0x51, 3, 0x07, 0xa1, 0x20, //
+        0, 0x90, 69, 100, //
+        96, 0x80, 69, 0, //
+        48, 0x90, 72, 127, //
+        // running status, and a note on without velocity as note off
+        96, 72, 0, //
+        0, 0xff, 0x2f, 0,
+    ];
+
+    #[test]
+    fn notes_and_velocities() {
+        let smf = Smf::parse(&SMF).unwrap();
+        assert_eq!(smf.nb_tracks(), 1);
+        assert!(smf.track(0).unwrap().eq([
+            Event::Vol {
+                vol: (MAX_VOL as i32 * 100 / 127) as i16
+            },
+            Event::Note {
+                pitch: A4,
+                us: 500_000
+            },
+            Event::Rest { us: 250_000 },
+            Event::Vol { vol: MAX_VOL },
+            Event::Note {
+                pitch: C5,
+                us: 500_000
+            },
+        ]));
+        assert_eq!(smf.ms_duration(), 1250);
+    }
+
+    #[test]
+    fn smpte_division() {
+        let mut data = SMF;
+        // -128 frames per second, not a valid SMPTE rate but no panic,
+        // and 4 ticks per frame
+        data[12] = 0x80;
+        data[13] = 4;
+        let smf = Smf::parse(&data).unwrap();
+        assert!(smf.track(0).unwrap().skip(1).eq([
+            Event::Note {
+                pitch: A4,
+                us: 187_500
+            },
+            Event::Rest { us: 93_750 },
+            Event::Vol { vol: MAX_VOL },
+            Event::Note {
+                pitch: C5,
+                us: 187_500
+            },
+        ]));
+        // 25 frames per second
+        data[12] = 0xe7;
+        assert_eq!(Smf::parse(&data).unwrap().ms_duration(), 2400);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(Smf::parse(b"RIFF").unwrap_err(), Error::NotSmf);
+        let mut data = SMF;
+        data[9] = 2;
+        assert_eq!(Smf::parse(&data).unwrap_err(), Error::UnsupportedFormat(2));
+        assert_eq!(
+            Smf::parse(&SMF[..40]).unwrap_err(),
+            Error::Truncated { offset: 40 }
+        );
+        let mut data = SMF;
+        // a length past the end of the file
+        data[18] = 0xff;
+        assert_eq!(
+            Smf::parse(&data).unwrap_err(),
+            Error::Truncated { offset: 48 }
+        );
+        let mut data = SMF;
+        // a data byte without a status
+        data[23] = 0x45;
+        assert_eq!(
+            Smf::parse(&data).unwrap_err(),
+            Error::BadEvent { offset: 23 }
+        );
+    }
+}
@@ -0,0 +1,306 @@
+use crate::songs::{ms_duration, Event};
+use crate::{pitch, MAX_VOL};
+
+// Music Macro Language compiler, for example `t185 l8 o5 e e4 e4 c e4
+// g2 <g2`. Supported commands:
+//  - `c d e f g a b`, followed by `+`/`#` (sharp) or `-` (flat), an
+//    optional length and dots; `&` ties to the next note of the same
+//    pitch and `^` extends the note by another length,
+//  - `r` or `p` for a rest, with the same length syntax,
+//  - `t` tempo in quarter notes per minute, `l` default length,
+//  - `o` octave, `<` and `>` to go one octave down or up,
+//  - `v` volume from 0 to 15,
+//  - `q` articulation from 1 (staccato) to 8 (legato).
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    UnknownCommand,
+    MissingNumber,
+    BadLength,
+    BadOctave,
+    BadTempo,
+    BadVolume,
+    BadArticulation,
+    BadTie,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Error {
+    // byte offsets of the faulty part of the source string
+    pub start: usize,
+    pub end: usize,
+    pub kind: ErrorKind,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Mml<'a> {
+    src: &'a str,
+}
+impl<'a> Mml<'a> {
+    pub fn new(src: &'a str) -> Result<Self, Error> {
+        let mut parser = Parser::new(src.as_bytes());
+        while parser.next_item()?.is_some() {}
+        Ok(Mml { src })
+    }
+    pub fn events(&self) -> Events<'a> {
+        Events {
+            parser: Parser::new(self.src.as_bytes()),
+            rest: None,
+        }
+    }
+    pub fn ms_duration(&self) -> u32 {
+        ms_duration(self.events())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Events<'a> {
+    parser: Parser<'a>,
+    rest: Option<u32>,
+}
+impl<'a> Iterator for Events<'a> {
+    type Item = Event;
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(us) = self.rest.take() {
+            return Some(Event::Rest { us });
+        }
+        let event = match self.parser.next_item().ok()?? {
+            Item::Note { key, us } => {
+                let note_us = (us as u64 * self.parser.pct as u64 / 100) as u32;
+                if us > note_us {
+                    self.rest = Some(us - note_us);
+                }
+                Event::Note {
+                    pitch: pitch::midi(key),
+                    us: note_us,
+                }
+            }
+            Item::Rest { us } => Event::Rest { us },
+            Item::Vol(vol) => Event::Vol { vol },
+        };
+        Some(event)
+    }
+}
+
+enum Item {
+    Note { key: u8, us: u32 },
+    Rest { us: u32 },
+    Vol(i16),
+}
+
+#[derive(Clone, Debug)]
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    octave: u8,
+    whole_us: u32,
+    // default length, and its number of dots
+    length: (u32, u32),
+    pct: u32,
+}
+impl<'a> Parser<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Parser {
+            bytes,
+            pos: 0,
+            octave: 4,
+            whole_us: 4 * 60 * 1_000_000 / 120,
+            length: (4, 0),
+            pct: 7 * 100 / 8,
+        }
+    }
+    fn err<T>(&self, start: usize, kind: ErrorKind) -> Result<T, Error> {
+        Err(Error {
+            start,
+            end: self.pos.max(start + 1),
+            kind,
+        })
+    }
+    fn peek(&mut self) -> Option<u8> {
+        while self.pos < self.bytes.len() && self.bytes[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+        self.bytes.get(self.pos).map(|b| b.to_ascii_lowercase())
+    }
+    fn eat(&mut self, c: u8) -> bool {
+        let res = self.peek() == Some(c);
+        if res {
+            self.pos += 1;
+        }
+        res
+    }
+    fn number(&mut self) -> Option<u32> {
+        self.peek();
+        let start = self.pos;
+        let mut res = 0u32;
+        while let Some(b) = self.bytes.get(self.pos).filter(|b| b.is_ascii_digit()) {
+            res = res.saturating_mul(10).saturating_add((b - b'0') as u32);
+            self.pos += 1;
+        }
+        if self.pos == start {
+            None
+        } else {
+            Some(res)
+        }
+    }
+    fn ranged(&mut self, min: u32, max: u32, kind: ErrorKind) -> Result<u32, Error> {
+        let start = self.pos;
+        match self.number() {
+            None => self.err(start, ErrorKind::MissingNumber),
+            Some(n) if n < min || n > max => self.err(start, kind),
+            Some(n) => Ok(n),
+        }
+    }
+    // a length and its dots, `None` for the length if there is no number
+    fn length_opt(&mut self) -> Result<(Option<u32>, u32), Error> {
+        let start = self.pos;
+        let len = match self.number() {
+            Some(len) if len == 0 || len > 192 => return self.err(start, ErrorKind::BadLength),
+            len => len,
+        };
+        let mut dots = 0;
+        while dots < 4 && self.eat(b'.') {
+            dots += 1;
+        }
+        Ok((len, dots))
+    }
+    fn length(&mut self) -> Result<(u32, u32), Error> {
+        Ok(match self.length_opt()? {
+            (Some(len), dots) => (len, dots),
+            (None, dots) => (self.length.0, (self.length.1 + dots).min(4)),
+        })
+    }
+    fn us(&self, (len, dots): (u32, u32)) -> u32 {
+        (self.whole_us as u64 * ((2 << dots) - 1) / (len << dots) as u64) as u32
+    }
+    // a note name and its accidental as a MIDI note number
+    fn key(&mut self, start: usize, name: u8) -> Result<u8, Error> {
+        let semitone = match name {
+            b'c' => 0,
+            b'd' => 2,
+            b'e' => 4,
+            b'f' => 5,
+            b'g' => 7,
+            b'a' => 9,
+            _ => 11,
+        };
+        let accidental = match self.bytes.get(self.pos) {
+            Some(b'+') | Some(b'#') => 1,
+            Some(b'-') => -1,
+            _ => 0,
+        };
+        if accidental != 0 {
+            self.pos += 1;
+        }
+        let key = 12 * (self.octave as i32 + 1) + semitone + accidental;
+        if !(0..128).contains(&key) {
+            return self.err(start, ErrorKind::BadOctave);
+        }
+        Ok(key as u8)
+    }
+    // the duration of a note or a rest, with its ties
+    fn duration(&mut self, key: Option<u8>) -> Result<u32, Error> {
+        let mut us = self.length().map(|l| self.us(l))?;
+        loop {
+            let start = self.pos;
+            if self.eat(b'^') {
+                us += self.length().map(|l| self.us(l))?;
+            } else if key.is_some() && self.eat(b'&') {
+                let name = match self.peek() {
+                    Some(name @ b'a'..=b'g') => name,
+                    _ => return self.err(start, ErrorKind::BadTie),
+                };
+                self.pos += 1;
+                if Some(self.key(start, name)?) != key {
+                    return self.err(start, ErrorKind::BadTie);
+                }
+                us += self.length().map(|l| self.us(l))?;
+            } else {
+                return Ok(us);
+            }
+        }
+    }
+    fn next_item(&mut self) -> Result<Option<Item>, Error> {
+        loop {
+            let c = match self.peek() {
+                None => return Ok(None),
+                Some(c) => c,
+            };
+            let start = self.pos;
+            self.pos += 1;
+            match c {
+                b'a'..=b'g' => {
+                    let key = self.key(start, c)?;
+                    let us = self.duration(Some(key))?;
+                    return Ok(Some(Item::Note { key, us }));
+                }
+                b'r' | b'p' => {
+                    let us = self.duration(None)?;
+                    return Ok(Some(Item::Rest { us }));
+                }
+                b'v' => {
+                    let v = self.ranged(0, 15, ErrorKind::BadVolume)?;
+                    return Ok(Some(Item::Vol((MAX_VOL as i32 * v as i32 / 15) as i16)));
+                }
+                b't' => {
+                    self.whole_us = 4 * 60 * 1_000_000 / self.ranged(1, 900, ErrorKind::BadTempo)?
+                }
+                b'l' => match self.length_opt()? {
+                    (Some(len), dots) => self.length = (len, dots),
+                    (None, _) => return self.err(start, ErrorKind::MissingNumber),
+                },
+                b'o' => self.octave = self.ranged(0, 8, ErrorKind::BadOctave)? as u8,
+                b'q' => self.pct = self.ranged(1, 8, ErrorKind::BadArticulation)? * 100 / 8,
+                b'<' if self.octave > 0 => self.octave -= 1,
+                b'>' if self.octave < 8 => self.octave += 1,
+                b'<' | b'>' => return self.err(start, ErrorKind::BadOctave),
+                _ => return self.err(start, ErrorKind::UnknownCommand),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notes_and_rests() {
+        let mml = Mml::new("t60 l8 o4 a c+4. r q8 e-&d+^16 v15 >c").unwrap();
+        let note = |key, us| Event::Note {
+            pitch: pitch::midi(key),
+            us,
+        };
+        let rest = |us| Event::Rest { us };
+        assert!(mml.events().eq([
+            note(69, 435_000),
+            rest(65_000),
+            note(61, 1_305_000),
+            rest(195_000),
+            rest(500_000),
+            note(63, 1_250_000),
+            Event::Vol { vol: MAX_VOL },
+            note(72, 500_000),
+        ]));
+        assert_eq!(mml.ms_duration(), 4250);
+    }
+
+    #[test]
+    fn errors() {
+        let error = |src| {
+            Mml::new(src)
+                .map(|_| ())
+                .map_err(|e| (e.start, e.end, e.kind))
+        };
+        assert_eq!(error("x"), Err((0, 1, ErrorKind::UnknownCommand)));
+        assert_eq!(error("l"), Err((0, 1, ErrorKind::MissingNumber)));
+        assert_eq!(error("c0"), Err((1, 2, ErrorKind::BadLength)));
+        assert_eq!(error("t0"), Err((1, 2, ErrorKind::BadTempo)));
+        assert_eq!(error("v16"), Err((1, 3, ErrorKind::BadVolume)));
+        assert_eq!(error("q9"), Err((1, 2, ErrorKind::BadArticulation)));
+        assert_eq!(error("o9"), Err((1, 2, ErrorKind::BadOctave)));
+        assert_eq!(error("o0 <"), Err((3, 4, ErrorKind::BadOctave)));
+        assert_eq!(error("c&d"), Err((1, 3, ErrorKind::BadTie)));
+        assert_eq!(error("r&c"), Err((1, 2, ErrorKind::UnknownCommand)));
+    }
+}
@@ -0,0 +1,365 @@
+use crate::oscillator::sin;
+use crate::{xorshift32, Env, Freq, Sound, MAX_VOL, RATE};
+
+// Modulation sources, and their routing to the parameters of a sound.
+// The sources run at the sample rate, but the parameters are only
+// updated every CONTROL_TICKS samples.
+
+pub const CONTROL_TICKS: u32 = 32;
+
+pub trait Modulator {
+    // between -MAX_VOL and MAX_VOL
+    fn value(&self) -> i16;
+    fn advance(&mut self);
+    // called when a note starts and when it stops
+    fn trigger(&mut self) {}
+    fn release(&mut self) {}
+    fn set_rate(&mut self, rate: u32);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LfoShape {
+    Sine,
+    Triangle,
+    Square,
+    SampleAndHold,
+}
+
+#[derive(Clone, Debug)]
+pub struct Lfo {
+    pub shape: LfoShape,
+    // restart the period at each note
+    pub key_sync: bool,
+    freq: Freq,
+    rate: u32,
+    // 2^32 is a full period
+    phase: u32,
+    inc: u32,
+    held: i16,
+    seed: u32,
+}
+impl Lfo {
+    pub fn new(shape: LfoShape, freq: Freq) -> Self {
+        let mut res = Lfo {
+            shape,
+            key_sync: false,
+            freq,
+            rate: RATE,
+            phase: 0,
+            inc: 0,
+            held: 0,
+            seed: 0x1234_5678,
+        };
+        res.set_freq(freq);
+        res
+    }
+    pub fn freq(&self) -> Freq {
+        self.freq
+    }
+    pub fn set_freq(&mut self, freq: Freq) {
+        self.freq = freq;
+        self.inc = (((freq.mhz() as u64) << 32) / (self.rate as u64 * 1000)) as u32;
+    }
+}
+impl Modulator for Lfo {
+    fn value(&self) -> i16 {
+        match self.shape {
+            LfoShape::Sine => (sin(self.phase) >> 15) as i16,
+            LfoShape::Triangle => {
+                // starting from 0 and going up, as the sine
+                let x = (self.phase.wrapping_add(1 << 30) >> 16) as i32;
+                let res = if x < 0x8000 { 2 * x } else { 2 * (0xffff - x) };
+                (res - MAX_VOL as i32).max(-(MAX_VOL as i32)) as i16
+            }
+            LfoShape::Square if self.phase < 1 << 31 => MAX_VOL,
+            LfoShape::Square => -MAX_VOL,
+            LfoShape::SampleAndHold => self.held,
+        }
+    }
+    fn advance(&mut self) {
+        let (phase, wrapped) = self.phase.overflowing_add(self.inc);
+        self.phase = phase;
+        if wrapped {
+            self.held = random(&mut self.seed);
+        }
+    }
+    fn trigger(&mut self) {
+        if self.key_sync {
+            self.phase = 0;
+            self.held = random(&mut self.seed);
+        }
+    }
+    fn set_rate(&mut self, rate: u32) {
+        self.rate = rate;
+        self.set_freq(self.freq);
+    }
+}
+
+// between -MAX_VOL and MAX_VOL, as the other shapes
+fn random(seed: &mut u32) -> i16 {
+    ((xorshift32(seed) >> 16) as i16).max(-MAX_VOL)
+}
+
+#[derive(Clone, Debug)]
+pub enum Source {
+    Lfo(Lfo),
+    Env(Env),
+}
+impl From<Lfo> for Source {
+    fn from(lfo: Lfo) -> Self {
+        Source::Lfo(lfo)
+    }
+}
+impl From<Env> for Source {
+    fn from(env: Env) -> Self {
+        Source::Env(env)
+    }
+}
+impl Modulator for Source {
+    fn value(&self) -> i16 {
+        match self {
+            Source::Lfo(lfo) => lfo.value(),
+            Source::Env(env) => env.value(),
+        }
+    }
+    fn advance(&mut self) {
+        match self {
+            Source::Lfo(lfo) => lfo.advance(),
+            Source::Env(env) => Modulator::advance(env),
+        }
+    }
+    fn trigger(&mut self) {
+        match self {
+            Source::Lfo(lfo) => lfo.trigger(),
+            Source::Env(env) => env.trigger(),
+        }
+    }
+    fn release(&mut self) {
+        match self {
+            Source::Lfo(lfo) => lfo.release(),
+            Source::Env(env) => env.release(),
+        }
+    }
+    fn set_rate(&mut self, rate: u32) {
+        match self {
+            Source::Lfo(lfo) => lfo.set_rate(rate),
+            Source::Env(env) => env.set_rate(rate),
+        }
+    }
+}
+
+// What a route modulates. The amount of the route is the modulation
+// at full scale of the source: in cents for `Freq`, scaled and given
+// to the function for `Param`. For `Vol`, the volume is unchanged at
+// the top of the source, and lowered by the amount (relative to
+// MAX_VOL) at its bottom. The routes to `Freq` and `Vol` add up.
+#[derive(Clone, Copy, Debug)]
+pub enum Target<S> {
+    Freq,
+    Vol,
+    Param(fn(&mut S, i16)),
+}
+
+#[derive(Clone, Debug)]
+pub struct Route<S> {
+    pub source: Source,
+    pub target: Target<S>,
+    pub amount: i16,
+}
+impl<S> Route<S> {
+    pub fn new<M: Into<Source>>(source: M, target: Target<S>, amount: i16) -> Self {
+        Route {
+            source: source.into(),
+            target,
+            amount,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Modulated<S, const N: usize> {
+    sound: S,
+    routes: [Route<S>; N],
+    // the frequency and volume before modulation
+    freq: Freq,
+    vol: i16,
+    ticks: u32,
+}
+impl<S: Sound, const N: usize> Modulated<S, N> {
+    pub fn new(sound: S, mut routes: [Route<S>; N]) -> Self {
+        for route in routes.iter_mut() {
+            route.source.set_rate(sound.rate());
+        }
+        Modulated {
+            vol: sound.vol(),
+            sound,
+            routes,
+            freq: Freq(0),
+            ticks: 0,
+        }
+    }
+    pub fn routes_mut(&mut self) -> &mut [Route<S>; N] {
+        &mut self.routes
+    }
+    fn update(&mut self) {
+        let mut millicents = 0i64;
+        let mut vol = MAX_VOL as i32;
+        let (mut bend, mut tremolo) = (false, false);
+        for route in self.routes.iter() {
+            let source = route.source.value() as i32;
+            let value = source * route.amount as i32;
+            match route.target {
+                Target::Freq => {
+                    millicents += value as i64 * 1000 / MAX_VOL as i64;
+                    bend = true;
+                }
+                Target::Vol => {
+                    vol -= (MAX_VOL as i32 - source) * route.amount as i32 / (2 * MAX_VOL as i32);
+                    tremolo = true;
+                }
+                Target::Param(f) => f(&mut self.sound, (value / MAX_VOL as i32) as i16),
+            }
+        }
+        if bend && self.freq != Freq(0) {
+            let millicents = millicents.clamp(i32::MIN as i64, i32::MAX as i64) as i32;
+            self.sound.bend(self.freq.add_millicents(millicents));
+        }
+        if tremolo {
+            let vol = self.vol as i32 * vol.clamp(0, MAX_VOL as i32) / MAX_VOL as i32;
+            self.sound.set_vol(vol as i16);
+        }
+    }
+}
+
+impl<S: Sound, const N: usize> Sound for Modulated<S, N> {
+    fn vol(&self) -> i16 {
+        self.vol
+    }
+    fn level(&self) -> i16 {
+        self.sound.level()
+    }
+    fn rate(&self) -> u32 {
+        self.sound.rate()
+    }
+    fn get(&self) -> i16 {
+        self.sound.get()
+    }
+    fn advance(&mut self) {
+        for route in self.routes.iter_mut() {
+            route.source.advance();
+        }
+        self.ticks += 1;
+        if self.ticks >= CONTROL_TICKS {
+            self.ticks = 0;
+            self.update();
+        }
+        self.sound.advance();
+    }
+    fn set_freq(&mut self, freq: Freq) {
+        self.freq = freq;
+        self.sound.set_freq(freq);
+        for route in self.routes.iter_mut() {
+            route.source.trigger();
+        }
+        self.update();
+    }
+    fn bend(&mut self, freq: Freq) {
+        self.freq = freq;
+        self.sound.bend(freq);
+        self.update();
+    }
+    fn sync(&mut self) {
+        self.sound.sync();
+    }
+    fn set_phase(&mut self, phase: u32) {
+        self.sound.set_phase(phase);
+    }
+    fn stop(&mut self) {
+        for route in self.routes.iter_mut() {
+            route.source.release();
+        }
+        self.sound.stop();
+    }
+    fn set_vol(&mut self, vol: i16) {
+        self.vol = vol;
+        self.sound.set_vol(vol);
+        self.update();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pitch::A4;
+    use crate::Oscillator;
+
+    // the values at each quarter of the period
+    fn quarters(shape: LfoShape) -> [i16; 4] {
+        let mut lfo = Lfo::new(shape, Freq::from_hz(1));
+        lfo.set_rate(4);
+        let mut res = [0; 4];
+        for v in res.iter_mut() {
+            *v = lfo.value();
+            lfo.advance();
+        }
+        res
+    }
+
+    #[test]
+    fn shapes() {
+        assert_eq!(quarters(LfoShape::Sine), [0, MAX_VOL, 0, -MAX_VOL]);
+        assert_eq!(quarters(LfoShape::Triangle), [1, MAX_VOL, -1, -MAX_VOL]);
+        assert_eq!(
+            quarters(LfoShape::Square),
+            [MAX_VOL, MAX_VOL, -MAX_VOL, -MAX_VOL]
+        );
+    }
+
+    #[test]
+    fn sample_and_hold() {
+        let mut lfo = Lfo::new(LfoShape::SampleAndHold, Freq::from_hz(1));
+        lfo.set_rate(2);
+        let mut min = 0;
+        for _ in 0..1 << 20 {
+            let held = lfo.value();
+            lfo.advance();
+            // held for the whole period
+            assert_eq!(lfo.value(), held);
+            lfo.advance();
+            min = min.min(held);
+        }
+        assert_eq!(min, -MAX_VOL);
+    }
+
+    // the rising zero crossings of the sound over `len` samples
+    fn crossings(sound: &mut impl Sound, len: u32) -> u32 {
+        let mut last = sound.step();
+        let mut res = 0;
+        for _ in 1..len {
+            let s = sound.step();
+            res += (last < 0 && s >= 0) as u32;
+            last = s;
+        }
+        res
+    }
+
+    #[test]
+    fn vibrato_and_tremolo() {
+        let square = || Lfo::new(LfoShape::Square, Freq::from_hz(1));
+        let mut sound = Modulated::new(
+            Oscillator::default(),
+            [
+                Route::new(square(), Target::Freq, 1200),
+                Route::new(square(), Target::Vol, MAX_VOL / 2),
+            ],
+        );
+        sound.set_freq(A4);
+        // an octave up and at full volume during the first half of the
+        // period, an octave down and at half volume during the second
+        assert_eq!(sound.level(), MAX_VOL);
+        assert!((crossings(&mut sound, RATE / 4) as i32 - 220).abs() <= 1);
+        crossings(&mut sound, RATE / 4 + CONTROL_TICKS);
+        assert_eq!(sound.level(), MAX_VOL / 2 + 1);
+        assert!((crossings(&mut sound, RATE / 4) as i32 - 55).abs() <= 1);
+    }
+}
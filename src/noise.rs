@@ -0,0 +1,158 @@
+use crate::{Freq, Sound, MAX_VOL, RATE};
+
+// Noise from a 16-bit linear feedback shift register. The register is
+// clocked at the frequency of the note, as on the sound chips of old
+// consoles: lower frequencies give a rougher, more metallic noise, and
+// a frequency of at least the sample rate gives white noise. The pink
+// noise uses the Voss-McCartney algorithm: rows of random values, each
+// one updated half as often as the previous one, are summed.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NoiseColor {
+    White,
+    Pink,
+}
+
+const ROWS: usize = 7;
+
+#[derive(Clone, Debug)]
+pub struct Noise {
+    pub color: NoiseColor,
+    rate: u32,
+    vol: i16,
+    freq: Freq,
+    playing: bool,
+    // 2^32 is a clock period of the register
+    phase: u32,
+    inc: u32,
+    lfsr: u16,
+    counter: u32,
+    rows: [i16; ROWS],
+    sample: i16,
+}
+impl Noise {
+    pub fn new(color: NoiseColor) -> Self {
+        Self::with_rate(color, RATE)
+    }
+    pub fn with_rate(color: NoiseColor, rate: u32) -> Self {
+        Noise {
+            color,
+            rate,
+            vol: MAX_VOL,
+            freq: Freq(0),
+            playing: false,
+            phase: 0,
+            inc: 0,
+            lfsr: 0xace1,
+            counter: 0,
+            rows: [0; ROWS],
+            sample: 0,
+        }
+    }
+    pub fn freq(&self) -> Freq {
+        self.freq
+    }
+    // the output bit of the register, as on the sound chips: the
+    // successive states are shifts of each other, so they are strongly
+    // correlated, but not the successive bits
+    fn random(&mut self) -> i16 {
+        // Galois LFSR with a maximal period
+        let lsb = self.lfsr & 1;
+        self.lfsr >>= 1;
+        if lsb != 0 {
+            self.lfsr ^= 0xb400;
+            MAX_VOL
+        } else {
+            -MAX_VOL
+        }
+    }
+    fn clock(&mut self) {
+        let white = self.random();
+        self.sample = match self.color {
+            NoiseColor::White => white,
+            NoiseColor::Pink => {
+                self.counter = self.counter.wrapping_add(1);
+                let row = self.counter.trailing_zeros() as usize;
+                if row < ROWS {
+                    self.rows[row] = self.random() >> 3;
+                }
+                self.rows.iter().fold(white >> 3, |acc, r| acc + r)
+            }
+        };
+    }
+}
+
+impl Sound for Noise {
+    fn vol(&self) -> i16 {
+        self.vol
+    }
+    fn rate(&self) -> u32 {
+        self.rate
+    }
+    fn get(&self) -> i16 {
+        if !self.playing {
+            return 0;
+        }
+        (self.sample as i32 * self.vol as i32 / MAX_VOL as i32) as i16
+    }
+    fn advance(&mut self) {
+        if !self.playing {
+            return;
+        }
+        let (phase, wrapped) = self.phase.overflowing_add(self.inc);
+        self.phase = phase;
+        if wrapped {
+            self.clock();
+        }
+    }
+    fn set_freq(&mut self, freq: Freq) {
+        self.freq = freq;
+        let inc = ((freq.mhz() as u64) << 32) / (self.rate as u64 * 1000);
+        self.inc = inc.min(u32::MAX as u64) as u32;
+        self.playing = true;
+    }
+    fn bend(&mut self, freq: Freq) {
+        if self.playing {
+            self.set_freq(freq);
+        }
+    }
+    fn stop(&mut self) {
+        self.playing = false;
+    }
+    fn set_vol(&mut self, vol: i16) {
+        self.vol = vol;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // lag-1 autocorrelation, in thousandths
+    fn autocorrelation(color: NoiseColor) -> i64 {
+        let mut noise = Noise::new(color);
+        noise.set_freq(Freq(RATE * 1000));
+        let (mut sum, mut sum_sq, mut sum_lag) = (0i64, 0i64, 0i64);
+        let mut last = noise.step() as i64;
+        let n = 10_000;
+        for _ in 0..n {
+            let x = noise.step() as i64;
+            sum += x;
+            sum_sq += x * x;
+            sum_lag += x * last;
+            last = x;
+        }
+        let mean = sum / n;
+        (sum_lag / n - mean * mean) * 1000 / (sum_sq / n - mean * mean)
+    }
+
+    #[test]
+    fn white_is_uncorrelated() {
+        assert!(autocorrelation(NoiseColor::White).abs() < 50);
+    }
+
+    #[test]
+    fn pink_is_correlated() {
+        assert!(autocorrelation(NoiseColor::Pink) > 300);
+    }
+}
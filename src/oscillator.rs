@@ -1,47 +1,56 @@
-use crate::{compute_ratio, MAX_VOL, RATE};
+use crate::{compute_ratio, Freq, MAX_VOL, RATE};
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct Oscillator {
     pub sample: &'static [i16; 256],
     vol: i16,
-    freq: u16,
-    step: u8,
-    modulo: u32,
-    cur_idx: u8,
-    cur_mod: u32,
+    freq: Freq,
+    table: TablePhase,
 }
 impl crate::Sound for Oscillator {
     fn vol(&self) -> i16 {
         self.vol
     }
-    fn set_freq(&mut self, freq: u16) {
+    fn rate(&self) -> u32 {
+        self.table.rate
+    }
+    fn set_freq(&mut self, freq: Freq) {
         self.freq = freq;
-        self.step = (256 * freq as u32 / RATE) as u8;
-        self.modulo = 256 * freq as u32 % RATE;
+        self.table.set_freq(freq);
+    }
+    fn bend(&mut self, freq: Freq) {
+        if self.table.is_running() {
+            self.set_freq(freq);
+        }
+    }
+    fn sync(&mut self) {
+        self.table.sync();
+    }
+    fn set_phase(&mut self, phase: u32) {
+        self.table.set_phase(phase);
     }
     fn set_vol(&mut self, vol: i16) {
         self.vol = vol;
     }
     fn get(&self) -> i16 {
-        let before = self.sample[self.cur_idx as usize];
-        let after = self.sample[self.cur_idx.wrapping_add(1) as usize];
-        let res = compute_ratio(before, after, self.cur_mod, RATE);
+        let res = self.table.read(self.sample);
         (res as i32 * self.vol as i32 / MAX_VOL as i32) as i16
     }
     fn advance(&mut self) {
-        let modulo = self.cur_mod + self.modulo;
-        self.cur_idx = (self.cur_idx as u32 + self.step as u32 + modulo / RATE) as u8;
-        self.cur_mod = modulo % RATE;
+        self.table.advance();
     }
     fn stop(&mut self) {
-        self.step = 0;
-        self.modulo = 0;
-        self.cur_idx = 0;
-        self.cur_mod = 0;
+        self.table.stop();
     }
 }
 impl Oscillator {
-    pub fn freq(&self) -> u16 {
+    pub fn with_rate(rate: u32) -> Self {
+        Self {
+            table: TablePhase::new(rate),
+            ..Self::default()
+        }
+    }
+    pub fn freq(&self) -> Freq {
         self.freq
     }
 }
@@ -49,14 +58,78 @@ impl Default for Oscillator {
     fn default() -> Self {
         Self {
             sample: &SIN,
-            freq: 0,
+            freq: Freq(0),
             vol: MAX_VOL,
+            table: TablePhase::new(RATE),
+        }
+    }
+}
+
+// The position in a table of 256 samples for a period: an index and a
+// remainder, in millihertz per sample rate, so that the frequencies
+// are exact.
+#[derive(Clone, Debug)]
+pub(crate) struct TablePhase {
+    pub(crate) rate: u32,
+    step: u8,
+    modulo: u32,
+    cur_idx: u8,
+    cur_mod: u32,
+}
+impl TablePhase {
+    pub(crate) fn new(rate: u32) -> Self {
+        TablePhase {
+            rate,
             step: 0,
             modulo: 0,
             cur_idx: 0,
             cur_mod: 0,
         }
     }
+    // the frequencies are in millihertz
+    fn denom(&self) -> u32 {
+        self.rate * 1000
+    }
+    pub(crate) fn is_running(&self) -> bool {
+        self.step != 0 || self.modulo != 0
+    }
+    pub(crate) fn set_freq(&mut self, freq: Freq) {
+        let num = 256 * freq.mhz() as u64;
+        let denom = self.denom() as u64;
+        self.step = (num / denom) as u8;
+        self.modulo = (num % denom) as u32;
+    }
+    pub(crate) fn sync(&mut self) {
+        self.cur_idx = 0;
+        self.cur_mod = 0;
+    }
+    pub(crate) fn set_phase(&mut self, phase: u32) {
+        self.cur_idx = (phase >> 24) as u8;
+        self.cur_mod = (((phase & 0xff_ffff) as u64 * self.denom() as u64) >> 24) as u32;
+    }
+    pub(crate) fn read(&self, table: &[i16; 256]) -> i16 {
+        let before = table[self.cur_idx as usize];
+        let after = table[self.cur_idx.wrapping_add(1) as usize];
+        compute_ratio(before, after, self.cur_mod, self.denom())
+    }
+    pub(crate) fn advance(&mut self) {
+        let denom = self.denom();
+        let modulo = self.cur_mod + self.modulo;
+        self.cur_idx = (self.cur_idx as u32 + self.step as u32 + modulo / denom) as u8;
+        self.cur_mod = modulo % denom;
+    }
+    pub(crate) fn stop(&mut self) {
+        *self = TablePhase::new(self.rate);
+    }
+}
+
+// sin(2π phase / 2^32) in Q30, interpolated from the table
+pub(crate) fn sin(phase: u32) -> i32 {
+    let idx = (phase >> 24) as u8;
+    let frac = ((phase >> 9) & 0x7fff) as i32;
+    let before = SIN[idx as usize] as i32;
+    let after = SIN[idx.wrapping_add(1) as usize] as i32;
+    (before << 15) + (after - before) * frac
 }
 
 pub static SIN: [i16; 256] = [
@@ -1,155 +1,571 @@
 #![allow(dead_code)]
 
-pub const B8: u16 = 7902;
-pub const AS8: u16 = 7459;
-pub const BF8: u16 = 7459;
-pub const A8: u16 = 7040;
-pub const GS8: u16 = 6645;
-pub const AF8: u16 = 6645;
-pub const G8: u16 = 6272;
-pub const FS8: u16 = 5920;
-pub const GF8: u16 = 5920;
-pub const F8: u16 = 5588;
-pub const E8: u16 = 5274;
-pub const DS8: u16 = 4978;
-pub const EF8: u16 = 4978;
-pub const D8: u16 = 4699;
-pub const CS8: u16 = 4435;
-pub const DF8: u16 = 4435;
-pub const C8: u16 = 4186;
-pub const B7: u16 = 3951;
-pub const AS7: u16 = 3729;
-pub const BF7: u16 = 3729;
-pub const A7: u16 = 3520;
-pub const GS7: u16 = 3322;
-pub const AF7: u16 = 3322;
-pub const G7: u16 = 3136;
-pub const FS7: u16 = 2960;
-pub const GF7: u16 = 2960;
-pub const F7: u16 = 2794;
-pub const E7: u16 = 2637;
-pub const DS7: u16 = 2489;
-pub const EF7: u16 = 2489;
-pub const D7: u16 = 2349;
-pub const CS7: u16 = 2217;
-pub const DF7: u16 = 2217;
-pub const C7: u16 = 2093;
-pub const B6: u16 = 1976;
-pub const AS6: u16 = 1865;
-pub const BF6: u16 = 1865;
-pub const A6: u16 = 1760;
-pub const GS6: u16 = 1661;
-pub const AF6: u16 = 1661;
-pub const G6: u16 = 1568;
-pub const FS6: u16 = 1480;
-pub const GF6: u16 = 1480;
-pub const F6: u16 = 1397;
-pub const E6: u16 = 1319;
-pub const DS6: u16 = 1245;
-pub const EF6: u16 = 1245;
-pub const D6: u16 = 1175;
-pub const CS6: u16 = 1109;
-pub const DF6: u16 = 1109;
-pub const C6: u16 = 1047;
-pub const B5: u16 = 988;
-pub const AS5: u16 = 932;
-pub const BF5: u16 = 932;
-pub const A5: u16 = 880;
-pub const GS5: u16 = 831;
-pub const AF5: u16 = 831;
-pub const G5: u16 = 784;
-pub const FS5: u16 = 740;
-pub const GF5: u16 = 740;
-pub const F5: u16 = 698;
-pub const E5: u16 = 659;
-pub const DS5: u16 = 622;
-pub const EF5: u16 = 622;
-pub const D5: u16 = 587;
-pub const CS5: u16 = 554;
-pub const DF5: u16 = 554;
-pub const C5: u16 = 523;
-pub const B4: u16 = 494;
-pub const AS4: u16 = 466;
-pub const BF4: u16 = 466;
-pub const A4: u16 = 440;
-pub const GS4: u16 = 415;
-pub const AF4: u16 = 415;
-pub const G4: u16 = 392;
-pub const FS4: u16 = 370;
-pub const GF4: u16 = 370;
-pub const F4: u16 = 349;
-pub const E4: u16 = 330;
-pub const DS4: u16 = 311;
-pub const EF4: u16 = 311;
-pub const D4: u16 = 294;
-pub const CS4: u16 = 277;
-pub const DF4: u16 = 277;
-pub const C4: u16 = 262;
-pub const B3: u16 = 247;
-pub const AS3: u16 = 233;
-pub const BF3: u16 = 233;
-pub const A3: u16 = 220;
-pub const GS3: u16 = 208;
-pub const AF3: u16 = 208;
-pub const G3: u16 = 196;
-pub const FS3: u16 = 185;
-pub const GF3: u16 = 185;
-pub const F3: u16 = 175;
-pub const E3: u16 = 165;
-pub const DS3: u16 = 156;
-pub const EF3: u16 = 156;
-pub const D3: u16 = 147;
-pub const CS3: u16 = 139;
-pub const DF3: u16 = 139;
-pub const C3: u16 = 131;
-pub const B2: u16 = 123;
-pub const AS2: u16 = 117;
-pub const BF2: u16 = 117;
-pub const A2: u16 = 110;
-pub const GS2: u16 = 104;
-pub const AF2: u16 = 104;
-pub const G2: u16 = 98;
-pub const FS2: u16 = 92;
-pub const GF2: u16 = 92;
-pub const F2: u16 = 87;
-pub const E2: u16 = 82;
-pub const DS2: u16 = 78;
-pub const EF2: u16 = 78;
-pub const D2: u16 = 73;
-pub const CS2: u16 = 69;
-pub const DF2: u16 = 69;
-pub const C2: u16 = 65;
-pub const B1: u16 = 62;
-pub const AS1: u16 = 58;
-pub const BF1: u16 = 58;
-pub const A1: u16 = 55;
-pub const GS1: u16 = 52;
-pub const AF1: u16 = 52;
-pub const G1: u16 = 49;
-pub const FS1: u16 = 46;
-pub const GF1: u16 = 46;
-pub const F1: u16 = 44;
-pub const E1: u16 = 41;
-pub const DS1: u16 = 39;
-pub const EF1: u16 = 39;
-pub const D1: u16 = 37;
-pub const CS1: u16 = 35;
-pub const DF1: u16 = 35;
-pub const C1: u16 = 33;
-pub const B0: u16 = 31;
-pub const AS0: u16 = 29;
-pub const BF0: u16 = 29;
-pub const A0: u16 = 28;
-pub const GS0: u16 = 26;
-pub const AF0: u16 = 26;
-pub const G0: u16 = 24;
-pub const FS0: u16 = 23;
-pub const GF0: u16 = 23;
-pub const F0: u16 = 22;
-pub const E0: u16 = 21;
-pub const DS0: u16 = 19;
-pub const EF0: u16 = 19;
-pub const D0: u16 = 18;
-pub const CS0: u16 = 17;
-pub const DF0: u16 = 17;
-pub const C0: u16 = 16;
+pub mod tuning;
+
+// A frequency, in millihertz.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Freq(pub u32);
+impl Freq {
+    pub const fn from_hz(hz: u16) -> Self {
+        Freq(hz as u32 * 1000)
+    }
+    pub const fn mhz(self) -> u32 {
+        self.0
+    }
+    // rounded to the nearest hertz
+    pub const fn hz(self) -> u32 {
+        (self.0 + 500) / 1000
+    }
+    // the frequency multiplied by num/den
+    pub fn mul_ratio(self, num: u32, den: u32) -> Self {
+        Freq((self.0 as u64 * num as u64 / den.max(1) as u64).min(u32::MAX as u64) as u32)
+    }
+    // the frequency shifted by the given number of cents, or of
+    // thousandths of a cent
+    pub fn add_cents(self, cents: i32) -> Self {
+        self.add_millicents(cents.saturating_mul(1000))
+    }
+    pub fn add_millicents(self, millicents: i32) -> Self {
+        self.mul_q32(cents_ratio(millicents as i64))
+    }
+    // the frequency multiplied by a Q32 fixed point ratio
+    pub(crate) fn mul_q32(self, ratio: u64) -> Self {
+        let res = (self.0 as u128 * ratio as u128 + (1 << 31)) >> 32;
+        Freq(res.min(u32::MAX as u128) as u32)
+    }
+}
+impl From<u16> for Freq {
+    fn from(hz: u16) -> Self {
+        Freq::from_hz(hz)
+    }
+}
+
+pub const B8: Freq = Freq(7_902_133);
+pub const AS8: Freq = Freq(7_458_620);
+pub const BF8: Freq = Freq(7_458_620);
+pub const A8: Freq = Freq(7_040_000);
+pub const GS8: Freq = Freq(6_644_875);
+pub const AF8: Freq = Freq(6_644_875);
+pub const G8: Freq = Freq(6_271_927);
+pub const FS8: Freq = Freq(5_919_911);
+pub const GF8: Freq = Freq(5_919_911);
+pub const F8: Freq = Freq(5_587_652);
+pub const E8: Freq = Freq(5_274_041);
+pub const DS8: Freq = Freq(4_978_032);
+pub const EF8: Freq = Freq(4_978_032);
+pub const D8: Freq = Freq(4_698_636);
+pub const CS8: Freq = Freq(4_434_922);
+pub const DF8: Freq = Freq(4_434_922);
+pub const C8: Freq = Freq(4_186_009);
+pub const B7: Freq = Freq(3_951_066);
+pub const AS7: Freq = Freq(3_729_310);
+pub const BF7: Freq = Freq(3_729_310);
+pub const A7: Freq = Freq(3_520_000);
+pub const GS7: Freq = Freq(3_322_438);
+pub const AF7: Freq = Freq(3_322_438);
+pub const G7: Freq = Freq(3_135_963);
+pub const FS7: Freq = Freq(2_959_955);
+pub const GF7: Freq = Freq(2_959_955);
+pub const F7: Freq = Freq(2_793_826);
+pub const E7: Freq = Freq(2_637_020);
+pub const DS7: Freq = Freq(2_489_016);
+pub const EF7: Freq = Freq(2_489_016);
+pub const D7: Freq = Freq(2_349_318);
+pub const CS7: Freq = Freq(2_217_461);
+pub const DF7: Freq = Freq(2_217_461);
+pub const C7: Freq = Freq(2_093_005);
+pub const B6: Freq = Freq(1_975_533);
+pub const AS6: Freq = Freq(1_864_655);
+pub const BF6: Freq = Freq(1_864_655);
+pub const A6: Freq = Freq(1_760_000);
+pub const GS6: Freq = Freq(1_661_219);
+pub const AF6: Freq = Freq(1_661_219);
+pub const G6: Freq = Freq(1_567_982);
+pub const FS6: Freq = Freq(1_479_978);
+pub const GF6: Freq = Freq(1_479_978);
+pub const F6: Freq = Freq(1_396_913);
+pub const E6: Freq = Freq(1_318_510);
+pub const DS6: Freq = Freq(1_244_508);
+pub const EF6: Freq = Freq(1_244_508);
+pub const D6: Freq = Freq(1_174_659);
+pub const CS6: Freq = Freq(1_108_731);
+pub const DF6: Freq = Freq(1_108_731);
+pub const C6: Freq = Freq(1_046_502);
+pub const B5: Freq = Freq(987_767);
+pub const AS5: Freq = Freq(932_328);
+pub const BF5: Freq = Freq(932_328);
+pub const A5: Freq = Freq(880_000);
+pub const GS5: Freq = Freq(830_609);
+pub const AF5: Freq = Freq(830_609);
+pub const G5: Freq = Freq(783_991);
+pub const FS5: Freq = Freq(739_989);
+pub const GF5: Freq = Freq(739_989);
+pub const F5: Freq = Freq(698_456);
+pub const E5: Freq = Freq(659_255);
+pub const DS5: Freq = Freq(622_254);
+pub const EF5: Freq = Freq(622_254);
+pub const D5: Freq = Freq(587_330);
+pub const CS5: Freq = Freq(554_365);
+pub const DF5: Freq = Freq(554_365);
+pub const C5: Freq = Freq(523_251);
+pub const B4: Freq = Freq(493_883);
+pub const AS4: Freq = Freq(466_164);
+pub const BF4: Freq = Freq(466_164);
+pub const A4: Freq = Freq(440_000);
+pub const GS4: Freq = Freq(415_305);
+pub const AF4: Freq = Freq(415_305);
+pub const G4: Freq = Freq(391_995);
+pub const FS4: Freq = Freq(369_994);
+pub const GF4: Freq = Freq(369_994);
+pub const F4: Freq = Freq(349_228);
+pub const E4: Freq = Freq(329_628);
+pub const DS4: Freq = Freq(311_127);
+pub const EF4: Freq = Freq(311_127);
+pub const D4: Freq = Freq(293_665);
+pub const CS4: Freq = Freq(277_183);
+pub const DF4: Freq = Freq(277_183);
+pub const C4: Freq = Freq(261_626);
+pub const B3: Freq = Freq(246_942);
+pub const AS3: Freq = Freq(233_082);
+pub const BF3: Freq = Freq(233_082);
+pub const A3: Freq = Freq(220_000);
+pub const GS3: Freq = Freq(207_652);
+pub const AF3: Freq = Freq(207_652);
+pub const G3: Freq = Freq(195_998);
+pub const FS3: Freq = Freq(184_997);
+pub const GF3: Freq = Freq(184_997);
+pub const F3: Freq = Freq(174_614);
+pub const E3: Freq = Freq(164_814);
+pub const DS3: Freq = Freq(155_563);
+pub const EF3: Freq = Freq(155_563);
+pub const D3: Freq = Freq(146_832);
+pub const CS3: Freq = Freq(138_591);
+pub const DF3: Freq = Freq(138_591);
+pub const C3: Freq = Freq(130_813);
+pub const B2: Freq = Freq(123_471);
+pub const AS2: Freq = Freq(116_541);
+pub const BF2: Freq = Freq(116_541);
+pub const A2: Freq = Freq(110_000);
+pub const GS2: Freq = Freq(103_826);
+pub const AF2: Freq = Freq(103_826);
+pub const G2: Freq = Freq(97_999);
+pub const FS2: Freq = Freq(92_499);
+pub const GF2: Freq = Freq(92_499);
+pub const F2: Freq = Freq(87_307);
+pub const E2: Freq = Freq(82_407);
+pub const DS2: Freq = Freq(77_782);
+pub const EF2: Freq = Freq(77_782);
+pub const D2: Freq = Freq(73_416);
+pub const CS2: Freq = Freq(69_296);
+pub const DF2: Freq = Freq(69_296);
+pub const C2: Freq = Freq(65_406);
+pub const B1: Freq = Freq(61_735);
+pub const AS1: Freq = Freq(58_270);
+pub const BF1: Freq = Freq(58_270);
+pub const A1: Freq = Freq(55_000);
+pub const GS1: Freq = Freq(51_913);
+pub const AF1: Freq = Freq(51_913);
+pub const G1: Freq = Freq(48_999);
+pub const FS1: Freq = Freq(46_249);
+pub const GF1: Freq = Freq(46_249);
+pub const F1: Freq = Freq(43_654);
+pub const E1: Freq = Freq(41_203);
+pub const DS1: Freq = Freq(38_891);
+pub const EF1: Freq = Freq(38_891);
+pub const D1: Freq = Freq(36_708);
+pub const CS1: Freq = Freq(34_648);
+pub const DF1: Freq = Freq(34_648);
+pub const C1: Freq = Freq(32_703);
+pub const B0: Freq = Freq(30_868);
+pub const AS0: Freq = Freq(29_135);
+pub const BF0: Freq = Freq(29_135);
+pub const A0: Freq = Freq(27_500);
+pub const GS0: Freq = Freq(25_957);
+pub const AF0: Freq = Freq(25_957);
+pub const G0: Freq = Freq(24_500);
+pub const FS0: Freq = Freq(23_125);
+pub const GF0: Freq = Freq(23_125);
+pub const F0: Freq = Freq(21_827);
+pub const E0: Freq = Freq(20_602);
+pub const DS0: Freq = Freq(19_445);
+pub const EF0: Freq = Freq(19_445);
+pub const D0: Freq = Freq(18_354);
+pub const CS0: Freq = Freq(17_324);
+pub const DF0: Freq = Freq(17_324);
+pub const C0: Freq = Freq(16_352);
+
+pub fn midi(note: u8) -> Freq {
+    MIDI[(note & 0x7f) as usize]
+}
+
+// the MIDI note number of the nearest 12-TET note
+pub fn midi_note(freq: Freq) -> u8 {
+    let idx = match MIDI.binary_search(&freq) {
+        Ok(idx) => return idx as u8,
+        Err(idx) => idx,
+    };
+    // the nearest in cents, by comparing freq² to below × above
+    let square = |f: &Freq| f.mhz() as u64 * f.mhz() as u64;
+    match (MIDI.get(idx.wrapping_sub(1)), MIDI.get(idx)) {
+        (Some(below), Some(above)) if square(&freq) < below.mhz() as u64 * above.mhz() as u64 => {
+            idx as u8 - 1
+        }
+        (Some(_), None) => idx as u8 - 1,
+        _ => idx as u8,
+    }
+}
+
+// a MIDI note number and a number of cents, relative to A4 = 440 Hz
+pub fn midi_cents(note: u8, cents: i32) -> Freq {
+    let cents = (note as i32 - 69) * 100 + cents;
+    A4.add_millicents(cents.saturating_mul(1000))
+}
+
+// the interval from `from` to `to`, in thousandths of a cent, by a
+// binary search on `cents_ratio`
+pub fn millicents(from: Freq, to: Freq) -> i32 {
+    if from.mhz() == 0 || to.mhz() == 0 {
+        return 0;
+    }
+    let ratio = ((to.mhz() as u128) << 32) / from.mhz() as u128;
+    // 32 octaves both ways cover any two frequencies
+    let (mut lo, mut hi) = (-38_400_000i64, 38_400_000i64);
+    while hi - lo > 1 {
+        let mid = (lo + hi) / 2;
+        if cents_ratio(mid) as u128 <= ratio {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    lo as i32
+}
+
+const ONE: u64 = 1 << 32;
+
+// 2^(millicents/1_200_000) as a Q32 fixed point number, saturating.
+// The table of cents is linearly interpolated, which is precise to
+// less than a millicent.
+pub(crate) fn cents_ratio(millicents: i64) -> u64 {
+    let octave = millicents.div_euclid(1_200_000);
+    let rem = millicents.rem_euclid(1_200_000) as u32;
+    let cents = (rem / 1000 % 100) as usize;
+    let frac = (rem % 1000) as u64;
+    let lo = ONE + CENTS[cents] as u64;
+    let hi = ONE + CENTS[cents + 1] as u64;
+    let semitones = ONE + SEMITONES[(rem / 100_000) as usize] as u64;
+    let res = ((semitones as u128 * (lo + (hi - lo) * frac / 1000) as u128) >> 32) as u64;
+    match octave {
+        o if o >= 31 => u64::MAX,
+        o if o >= 0 => res << o,
+        o if o > -64 => res >> -o,
+        _ => 0,
+    }
+}
+
+// fractional parts of 2^(i/12) and 2^(i/1200), in Q32
+static SEMITONES: [u32; 12] = [
+    0,
+    255_392_046,
+    525_970_492,
+    812_638_371,
+    1_116_352_409,
+    1_438_126_223,
+    1_779_033_704,
+    2_140_212_599,
+    2_522_868_308,
+    2_928_277_910,
+    3_357_794_421,
+    3_812_851_313,
+];
+static CENTS: [u32; 101] = [
+    0,
+    2_481_587,
+    4_964_608,
+    7_449_063,
+    9_934_954,
+    12_422_282,
+    14_911_046,
+    17_401_249,
+    19_892_890,
+    22_385_971,
+    24_880_492,
+    27_376_455,
+    29_873_860,
+    32_372_708,
+    34_873_000,
+    37_374_736,
+    39_877_918,
+    42_382_546,
+    44_888_621,
+    47_396_144,
+    49_905_116,
+    52_415_538,
+    54_927_410,
+    57_440_733,
+    59_955_509,
+    62_471_738,
+    64_989_420,
+    67_508_558,
+    70_029_150,
+    72_551_200,
+    75_074_706,
+    77_599_670,
+    80_126_094,
+    82_653_977,
+    85_183_320,
+    87_714_125,
+    90_246_393,
+    92_780_123,
+    95_315_318,
+    97_851_977,
+    100_390_102,
+    102_929_693,
+    105_470_752,
+    108_013_279,
+    110_557_275,
+    113_102_741,
+    115_649_677,
+    118_198_085,
+    120_747_966,
+    123_299_320,
+    125_852_148,
+    128_406_451,
+    130_962_230,
+    133_519_486,
+    136_078_219,
+    138_638_431,
+    141_200_121,
+    143_763_292,
+    146_327_944,
+    148_894_078,
+    151_461_695,
+    154_030_795,
+    156_601_379,
+    159_173_449,
+    161_747_004,
+    164_322_047,
+    166_898_578,
+    169_476_597,
+    172_056_106,
+    174_637_105,
+    177_219_596,
+    179_803_578,
+    182_389_054,
+    184_976_024,
+    187_564_488,
+    190_154_448,
+    192_745_904,
+    195_338_857,
+    197_933_309,
+    200_529_260,
+    203_126_711,
+    205_725_662,
+    208_326_115,
+    210_928_071,
+    213_531_530,
+    216_136_494,
+    218_742_962,
+    221_350_936,
+    223_960_418,
+    226_571_407,
+    229_183_904,
+    231_797_911,
+    234_413_429,
+    237_030_457,
+    239_648_998,
+    242_269_052,
+    244_890_619,
+    247_513_702,
+    250_138_300,
+    252_764_414,
+    255_392_046,
+];
+
+static MIDI: [Freq; 128] = [
+    Freq(8_176),
+    Freq(8_662),
+    Freq(9_177),
+    Freq(9_723),
+    Freq(10_301),
+    Freq(10_913),
+    Freq(11_562),
+    Freq(12_250),
+    Freq(12_978),
+    Freq(13_750),
+    Freq(14_568),
+    Freq(15_434),
+    C0,
+    CS0,
+    D0,
+    DS0,
+    E0,
+    F0,
+    FS0,
+    G0,
+    GS0,
+    A0,
+    AS0,
+    B0,
+    C1,
+    CS1,
+    D1,
+    DS1,
+    E1,
+    F1,
+    FS1,
+    G1,
+    GS1,
+    A1,
+    AS1,
+    B1,
+    C2,
+    CS2,
+    D2,
+    DS2,
+    E2,
+    F2,
+    FS2,
+    G2,
+    GS2,
+    A2,
+    AS2,
+    B2,
+    C3,
+    CS3,
+    D3,
+    DS3,
+    E3,
+    F3,
+    FS3,
+    G3,
+    GS3,
+    A3,
+    AS3,
+    B3,
+    C4,
+    CS4,
+    D4,
+    DS4,
+    E4,
+    F4,
+    FS4,
+    G4,
+    GS4,
+    A4,
+    AS4,
+    B4,
+    C5,
+    CS5,
+    D5,
+    DS5,
+    E5,
+    F5,
+    FS5,
+    G5,
+    GS5,
+    A5,
+    AS5,
+    B5,
+    C6,
+    CS6,
+    D6,
+    DS6,
+    E6,
+    F6,
+    FS6,
+    G6,
+    GS6,
+    A6,
+    AS6,
+    B6,
+    C7,
+    CS7,
+    D7,
+    DS7,
+    E7,
+    F7,
+    FS7,
+    G7,
+    GS7,
+    A7,
+    AS7,
+    B7,
+    C8,
+    CS8,
+    D8,
+    DS8,
+    E8,
+    F8,
+    FS8,
+    G8,
+    GS8,
+    A8,
+    AS8,
+    B8,
+    Freq(8_372_018),
+    Freq(8_869_844),
+    Freq(9_397_273),
+    Freq(9_956_063),
+    Freq(10_548_082),
+    Freq(11_175_303),
+    Freq(11_839_822),
+    Freq(12_543_854),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn freq_units() {
+        assert_eq!(Freq::from_hz(440), A4);
+        assert_eq!(Freq(440_499).hz(), 440);
+        assert_eq!(Freq(440_500).hz(), 441);
+        assert_eq!(Freq::from(440), A4);
+    }
+
+    #[test]
+    fn mul_ratio() {
+        assert_eq!(A4.mul_ratio(3, 2), Freq(660_000));
+        assert_eq!(A4.mul_ratio(1, 0), A4);
+        assert_eq!(Freq(u32::MAX).mul_ratio(2, 1), Freq(u32::MAX));
+    }
+
+    #[test]
+    fn cents() {
+        assert_eq!(cents_ratio(0), ONE);
+        assert_eq!(cents_ratio(1_200_000), 2 * ONE);
+        assert_eq!(cents_ratio(-2_400_000), ONE / 4);
+        // 2^(1/12) and 2^(-1/1200)
+        assert!((cents_ratio(100_000) as i64 - 4_550_359_342).abs() < 10);
+        assert!((cents_ratio(-1000) as i64 - 4_292_487_142).abs() < 10);
+        assert_eq!(cents_ratio(40_000_000), u64::MAX);
+        assert_eq!(A4.add_cents(1200), A5);
+        assert_eq!(A4.add_cents(-1200), A3);
+        assert_eq!(A4.add_cents(300), C5);
+    }
+
+    #[test]
+    fn midi_notes() {
+        assert_eq!(midi(69), A4);
+        assert_eq!(midi_cents(69, 0), A4);
+        assert_eq!(midi_cents(60, 0).hz(), C4.hz());
+        assert_eq!(midi_cents(69, 50), A4.add_cents(50));
+        assert_eq!(midi_note(A4), 69);
+        // rounded in cents
+        assert_eq!(midi_note(A4.add_cents(49)), 69);
+        assert_eq!(midi_note(A4.add_cents(51)), 70);
+        assert_eq!(midi_note(Freq(1)), 0);
+        assert_eq!(midi_note(Freq(u32::MAX)), 127);
+    }
+
+    #[test]
+    fn intervals() {
+        assert_eq!(millicents(A4, A4), 0);
+        assert!((millicents(A4, A5) - 1_200_000).abs() <= 1);
+        assert!((millicents(A5, A4) + 1_200_000).abs() <= 1);
+        assert!((millicents(A4, E5) - 700_000).abs() < 100);
+        assert_eq!(millicents(Freq(0), A4), 0);
+    }
+}
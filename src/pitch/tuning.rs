@@ -0,0 +1,341 @@
+use super::{cents_ratio, Freq, A4, ONE};
+
+// Tuning systems: a scale gives the ratios of its degrees to its
+// first one, the last degree being the period of the scale (usually
+// the octave) as in Scala `.scl` files. A tuning maps the scale on the
+// MIDI note numbers, from a reference note and its frequency.
+
+pub const MAX_DEGREES: usize = 128;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Degree {
+    // in thousandths of a cent
+    Cents(i32),
+    Ratio(u32, u32),
+}
+impl Degree {
+    // as a Q32 fixed point ratio
+    fn ratio(self) -> u64 {
+        match self {
+            Degree::Cents(millicents) => cents_ratio(millicents as i64),
+            Degree::Ratio(num, den) => {
+                let res = ((num as u128) << 32) / den.max(1) as u128;
+                res.min(u64::MAX as u128) as u64
+            }
+        }
+    }
+}
+
+use Degree::*;
+
+pub const EQUAL: [Degree; 12] = [
+    Cents(100_000),
+    Cents(200_000),
+    Cents(300_000),
+    Cents(400_000),
+    Cents(500_000),
+    Cents(600_000),
+    Cents(700_000),
+    Cents(800_000),
+    Cents(900_000),
+    Cents(1_000_000),
+    Cents(1_100_000),
+    Cents(1_200_000),
+];
+pub const JUST: [Degree; 12] = [
+    Ratio(16, 15),
+    Ratio(9, 8),
+    Ratio(6, 5),
+    Ratio(5, 4),
+    Ratio(4, 3),
+    Ratio(45, 32),
+    Ratio(3, 2),
+    Ratio(8, 5),
+    Ratio(5, 3),
+    Ratio(9, 5),
+    Ratio(15, 8),
+    Ratio(2, 1),
+];
+pub const PYTHAGOREAN: [Degree; 12] = [
+    Ratio(256, 243),
+    Ratio(9, 8),
+    Ratio(32, 27),
+    Ratio(81, 64),
+    Ratio(4, 3),
+    Ratio(729, 512),
+    Ratio(3, 2),
+    Ratio(128, 81),
+    Ratio(27, 16),
+    Ratio(16, 9),
+    Ratio(243, 128),
+    Ratio(2, 1),
+];
+// quarter-comma meantone
+pub const MEANTONE: [Degree; 12] = [
+    Cents(76_049),
+    Cents(193_157),
+    Cents(310_265),
+    Cents(386_314),
+    Cents(503_422),
+    Cents(579_471),
+    Cents(696_578),
+    Cents(772_627),
+    Cents(889_735),
+    Cents(1_006_843),
+    Cents(1_082_892),
+    Cents(1_200_000),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    MissingCount,
+    BadCount,
+    TooManyDegrees,
+    BadPitch,
+    MissingPitch,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Error {
+    // line number, starting from 1
+    pub line: usize,
+    pub kind: ErrorKind,
+}
+
+#[derive(Clone, Debug)]
+pub struct Scale {
+    // Q32 ratios of the degrees after the first one
+    ratios: [u64; MAX_DEGREES],
+    len: usize,
+}
+impl Scale {
+    // Degrees beyond MAX_DEGREES are ignored.
+    pub fn new(degrees: &[Degree]) -> Self {
+        let mut ratios = [0; MAX_DEGREES];
+        let len = degrees.len().min(MAX_DEGREES);
+        for (r, d) in ratios.iter_mut().zip(degrees) {
+            *r = d.ratio();
+        }
+        Scale { ratios, len }
+    }
+    // Parses the content of a Scala `.scl` file: a description line, the
+    // number of degrees, and then one degree per line, in cents if it
+    // contains a dot, else as a ratio. Lines starting with `!` are
+    // comments.
+    pub fn parse_scl(src: &str) -> Result<Self, Error> {
+        let mut lines = src
+            .lines()
+            .enumerate()
+            .map(|(i, l)| (i + 1, l.trim()))
+            .filter(|(_, l)| !l.starts_with('!'));
+        let end = src.lines().count() + 1;
+        let err = |line, kind| Err(Error { line, kind });
+
+        // the description may be empty
+        lines.next();
+        let (line, count) = match lines.next() {
+            None => return err(end, ErrorKind::MissingCount),
+            Some(l) => l,
+        };
+        let count: usize = match first_word(count).parse() {
+            Err(_) => return err(line, ErrorKind::BadCount),
+            Ok(count) if count > MAX_DEGREES => return err(line, ErrorKind::TooManyDegrees),
+            Ok(count) => count,
+        };
+        let mut res = Scale {
+            ratios: [0; MAX_DEGREES],
+            len: count,
+        };
+        for r in res.ratios[..count].iter_mut() {
+            let (line, pitch) = match lines.next() {
+                None => return err(end, ErrorKind::MissingPitch),
+                Some(l) => l,
+            };
+            match parse_degree(first_word(pitch)) {
+                None => return err(line, ErrorKind::BadPitch),
+                Some(d) => *r = d.ratio(),
+            }
+        }
+        Ok(res)
+    }
+    pub fn nb_degrees(&self) -> usize {
+        self.len
+    }
+    // the Q32 ratio of the degree `steps` away from the first one,
+    // saturating
+    fn ratio(&self, steps: i32) -> u64 {
+        if self.len == 0 {
+            return ONE;
+        }
+        let len = self.len as i32;
+        let (periods, degree) = (steps.div_euclid(len), steps.rem_euclid(len));
+        let period = self.ratios[self.len - 1].max(1) as u128;
+        let mut res = match degree {
+            0 => ONE,
+            d => self.ratios[d as usize - 1],
+        } as u128;
+        for _ in 0..periods {
+            res = ((res * period) >> 32).min(u64::MAX as u128);
+        }
+        for _ in periods..0 {
+            res = (res << 32) / period;
+        }
+        res as u64
+    }
+}
+
+fn first_word(s: &str) -> &str {
+    s.split_whitespace().next().unwrap_or("")
+}
+
+fn parse_degree(s: &str) -> Option<Degree> {
+    if s.contains('.') {
+        return parse_cents(s).map(Cents);
+    }
+    let (num, den) = match s.find('/') {
+        Some(i) => (&s[..i], &s[i + 1..]),
+        None => (s, "1"),
+    };
+    match (parse_digits(num)?, parse_digits(den)?) {
+        (0, _) | (_, 0) => None,
+        (num, den) => Some(Ratio(num, den)),
+    }
+}
+
+// cents with a decimal part, in thousandths of a cent
+fn parse_cents(s: &str) -> Option<i32> {
+    let (neg, s) = match s.as_bytes().first() {
+        Some(b'-') => (true, &s[1..]),
+        Some(b'+') => (false, &s[1..]),
+        _ => (false, s),
+    };
+    let dot = s.find('.')?;
+    let int = match &s[..dot] {
+        "" => 0,
+        int => parse_digits(int)?,
+    };
+    let frac = &s[dot + 1..];
+    if !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let mut res = (int.min(i32::MAX as u32) as i32).checked_mul(1000)?;
+    for (b, unit) in frac.bytes().zip([100, 10, 1].iter()) {
+        res = res.checked_add((b - b'0') as i32 * unit)?;
+    }
+    Some(if neg { -res } else { res })
+}
+
+fn parse_digits(s: &str) -> Option<u32> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+#[derive(Clone, Debug)]
+pub struct Tuning {
+    scale: Scale,
+    // MIDI note of the first degree of the scale, and its frequency
+    root: u8,
+    root_freq: Freq,
+}
+impl Tuning {
+    // The scale starts on the `root` note, and the `reference` note
+    // sounds at `freq`.
+    pub fn new(scale: Scale, root: u8, reference: u8, freq: Freq) -> Self {
+        let ratio = scale.ratio(reference as i32 - root as i32).max(1) as u128;
+        let root_freq = (((freq.mhz() as u128) << 32) + ratio / 2) / ratio;
+        Tuning {
+            scale,
+            root,
+            root_freq: Freq(root_freq.min(u32::MAX as u128) as u32),
+        }
+    }
+    // 12-TET with the given frequency for A4
+    pub fn equal(a4: Freq) -> Self {
+        Self::new(Scale::new(&EQUAL), 60, 69, a4)
+    }
+    pub fn scale(&self) -> &Scale {
+        &self.scale
+    }
+    pub fn freq(&self, note: u8) -> Freq {
+        let ratio = self.scale.ratio(note as i32 - self.root as i32);
+        self.root_freq.mul_q32(ratio)
+    }
+    pub fn freq_cents(&self, note: u8, cents: i32) -> Freq {
+        self.freq(note).add_cents(cents)
+    }
+}
+impl Default for Tuning {
+    fn default() -> Self {
+        Self::equal(A4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{A5, C4};
+    use super::*;
+
+    #[test]
+    fn cents_overflow() {
+        for src in [
+            "overflow\n1\n2147483.999\n",
+            "overflow\n1\n3000000.0\n",
+            "overflow\n1\n-99999999999.5\n",
+        ] {
+            let err = Scale::parse_scl(src).unwrap_err();
+            assert_eq!(
+                err,
+                Error {
+                    line: 3,
+                    kind: ErrorKind::BadPitch
+                }
+            );
+        }
+    }
+
+    // within a millihertz of the rounding of the root frequency
+    fn close(a: Freq, b: Freq) -> bool {
+        (a.mhz() as i64 - b.mhz() as i64).abs() <= 1
+    }
+
+    #[test]
+    fn equal_tuning() {
+        let tuning = Tuning::default();
+        assert!(close(tuning.freq(69), A4));
+        assert!(close(tuning.freq(81), A5));
+        assert_eq!(tuning.freq(60).hz(), C4.hz());
+        assert_eq!(tuning.freq_cents(69, 1200), tuning.freq(69).mul_ratio(2, 1));
+    }
+
+    #[test]
+    fn just_tuning() {
+        let tuning = Tuning::new(Scale::new(&JUST), 60, 69, A4);
+        assert_eq!(tuning.freq(60), Freq(264_000));
+        assert_eq!(tuning.freq(67), Freq(396_000));
+        assert_eq!(tuning.freq(72), Freq(528_000));
+        assert_eq!(tuning.freq(48), Freq(132_000));
+    }
+
+    #[test]
+    fn scala() {
+        let src = "! comment\nfifths\n 3\n!\n 701.955 fifth\n3/2\n2\n";
+        let scale = Scale::parse_scl(src).unwrap();
+        assert_eq!(scale.nb_degrees(), 3);
+        assert_eq!(scale.ratio(3), 2 * ONE);
+        assert!((scale.ratio(1) as i64 - scale.ratio(2) as i64).abs() < 1000);
+    }
+
+    #[test]
+    fn scala_errors() {
+        let err = |line, kind| Err(Error { line, kind });
+        let parse = |src| Scale::parse_scl(src).map(|s| s.nb_degrees());
+        assert_eq!(parse("name\n"), err(2, ErrorKind::MissingCount));
+        assert_eq!(parse("name\nthree\n"), err(2, ErrorKind::BadCount));
+        assert_eq!(parse("name\n200\n"), err(2, ErrorKind::TooManyDegrees));
+        assert_eq!(parse("name\n2\n3/2\n"), err(4, ErrorKind::MissingPitch));
+        assert_eq!(parse("name\n1\n0/1\n"), err(3, ErrorKind::BadPitch));
+        assert_eq!(parse("name\n1\n1.2.3\n"), err(3, ErrorKind::BadPitch));
+    }
+}
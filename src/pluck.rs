@@ -0,0 +1,179 @@
+use crate::{xorshift32, Freq, Sound, MAX_VOL, RATE};
+
+// Karplus-Strong plucked string: a delay line of the length of a
+// period is filled with noise, and then fed back through an averaging
+// filter that makes the high harmonics die first. The length of the
+// delay line is fractional, read with a linear interpolation, so that
+// high notes stay in tune. N is the longest period, in samples.
+
+#[derive(Clone, Debug)]
+pub struct Pluck<const N: usize> {
+    rate: u32,
+    vol: i16,
+    freq: Freq,
+    // thousandths of the amplitude lost at each period
+    pub damping: u16,
+    buf: [i16; N],
+    // position of the last sample
+    pos: usize,
+    // delay, in Q16 samples, between the last sample and the first one
+    // read to compute the next
+    delay: u32,
+    playing: bool,
+    released: bool,
+    // Q15 gain fading out after release
+    fade: u32,
+    seed: u32,
+}
+impl<const N: usize> Pluck<N> {
+    pub fn new() -> Self {
+        Self::with_rate(RATE)
+    }
+    pub fn with_rate(rate: u32) -> Self {
+        Pluck {
+            rate,
+            vol: MAX_VOL,
+            freq: Freq(0),
+            damping: 2,
+            buf: [0; N],
+            pos: 0,
+            delay: 0,
+            playing: false,
+            released: false,
+            fade: 1 << 15,
+            seed: 0x1234_5678,
+        }
+    }
+    pub fn freq(&self) -> Freq {
+        self.freq
+    }
+    fn read(&self, delay: u32) -> i32 {
+        let (int, frac) = ((delay >> 16) as usize, (delay & 0xffff) as i64);
+        let a = self.buf[(self.pos + N - int % N) % N] as i64;
+        let b = self.buf[(self.pos + 2 * N - int % N - 1) % N] as i64;
+        (a + (((b - a) * frac) >> 16)) as i32
+    }
+}
+impl<const N: usize> Default for Pluck<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Sound for Pluck<N> {
+    fn vol(&self) -> i16 {
+        self.vol
+    }
+    fn rate(&self) -> u32 {
+        self.rate
+    }
+    fn get(&self) -> i16 {
+        if !self.playing || N == 0 {
+            return 0;
+        }
+        let res = self.buf[self.pos] as i32 * self.vol as i32 / MAX_VOL as i32;
+        ((res * self.fade as i32) >> 15) as i16
+    }
+    fn advance(&mut self) {
+        if !self.playing || N == 0 {
+            return;
+        }
+        let avg = (self.read(self.delay) + self.read(self.delay + (1 << 16))) / 2;
+        let gain = (1000 - self.damping.min(1000) as i32) * (1 << 15) / 1000;
+        self.pos = (self.pos + 1) % N;
+        // rounded towards 0, else negative samples never die
+        self.buf[self.pos] = (avg * gain / (1 << 15)) as i16;
+        if self.released {
+            self.fade = self.fade * 255 / 256;
+            self.playing = self.fade > 0;
+        }
+    }
+    fn set_freq(&mut self, freq: Freq) {
+        let mut sum = 0;
+        for s in self.buf.iter_mut() {
+            *s = (xorshift32(&mut self.seed) >> 16) as i16 / 2;
+            sum += *s as i32;
+        }
+        // without DC offset
+        let mean = sum / N.max(1) as i32;
+        for s in self.buf.iter_mut() {
+            *s -= mean as i16;
+        }
+        self.playing = true;
+        self.released = false;
+        self.fade = 1 << 15;
+        self.bend(freq);
+    }
+    fn bend(&mut self, freq: Freq) {
+        self.freq = freq;
+        // a period, minus the sample of the feedback and the half
+        // sample of the averaging
+        let period = ((self.rate as u64 * 1000) << 16) / (freq.mhz() as u64).max(1);
+        let max = (N.saturating_sub(3) as u64) << 16;
+        self.delay = period.saturating_sub(3 << 15).min(max) as u32;
+    }
+    fn stop(&mut self) {
+        self.released = true;
+    }
+    fn set_vol(&mut self, vol: i16) {
+        self.vol = vol;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples<const N: usize>(pluck: &mut Pluck<N>) -> [i16; 2048] {
+        let mut res = [0; 2048];
+        for s in res.iter_mut() {
+            *s = pluck.step();
+        }
+        res
+    }
+    fn energy(samples: &[i16]) -> i64 {
+        samples.iter().map(|&s| s as i64 * s as i64).sum()
+    }
+
+    #[test]
+    fn fundamental() {
+        let mut pluck = Pluck::<512>::new();
+        pluck.set_freq(Freq::from_hz(220));
+        for _ in 0..RATE / 10 {
+            pluck.step();
+        }
+        let s = samples(&mut pluck);
+        // the autocorrelation peaks at the period, 218.2 samples
+        let correlation = |lag: usize| {
+            (0..1024)
+                .map(|i| s[i] as i64 * s[i + lag] as i64)
+                .sum::<i64>()
+        };
+        let period = (150..300).max_by_key(|&lag| correlation(lag)).unwrap();
+        assert!((217..=219).contains(&period), "{}", period);
+    }
+
+    #[test]
+    fn decay() {
+        let mut pluck = Pluck::<512>::new();
+        pluck.set_freq(Freq::from_hz(220));
+        let mut last = energy(&samples(&mut pluck));
+        for _ in 0..20 {
+            let e = energy(&samples(&mut pluck));
+            assert!(e < last);
+            last = e;
+        }
+        // more damping dies faster
+        let mut damped = Pluck::<512>::new();
+        damped.damping = 20;
+        damped.set_freq(Freq::from_hz(220));
+        for _ in 0..20 {
+            samples(&mut damped);
+        }
+        assert!(energy(&samples(&mut damped)) < last / 10);
+        // and after release, it fades out quickly
+        pluck.stop();
+        samples(&mut pluck);
+        assert!(samples(&mut pluck).iter().all(|&s| s == 0));
+    }
+}
@@ -0,0 +1,183 @@
+use crate::{Freq, Sound, MAX_VOL, RATE};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stealing {
+    // steal the voice that was started first
+    Oldest,
+    // steal the voice with the lowest current level
+    Quietest,
+    // retrigger the voice already playing the same note if any, else
+    // steal the oldest one
+    SameNote,
+}
+
+#[derive(Clone, Debug)]
+struct Voice<S> {
+    sound: S,
+    key: Option<Freq>,
+    age: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct Polyphony<S, const N: usize> {
+    voices: [Voice<S>; N],
+    pub stealing: Stealing,
+    vol: i16,
+    age: u32,
+}
+impl<S: Sound + Clone, const N: usize> Polyphony<S, N> {
+    pub fn new(sound: S, stealing: Stealing) -> Self {
+        Self {
+            voices: core::array::from_fn(|_| Voice {
+                sound: sound.clone(),
+                key: None,
+                age: 0,
+            }),
+            stealing,
+            vol: MAX_VOL,
+            age: 0,
+        }
+    }
+}
+impl<S: Sound, const N: usize> Polyphony<S, N> {
+    pub fn note_on(&mut self, freq: Freq) {
+        if N == 0 {
+            return;
+        }
+        let idx = self.choose_voice(freq);
+        self.age = self.age.wrapping_add(1);
+        let voice = &mut self.voices[idx];
+        voice.key = Some(freq);
+        voice.age = self.age;
+        voice.sound.set_freq(freq);
+    }
+    pub fn note_off(&mut self, freq: Freq) {
+        for voice in self.voices.iter_mut().filter(|v| v.key == Some(freq)) {
+            voice.key = None;
+            voice.sound.stop();
+        }
+    }
+    pub fn voices(&self) -> impl Iterator<Item = &S> {
+        self.voices.iter().map(|v| &v.sound)
+    }
+    fn choose_voice(&self, freq: Freq) -> usize {
+        if self.stealing == Stealing::SameNote {
+            if let Some(idx) = self.voices.iter().position(|v| v.key == Some(freq)) {
+                return idx;
+            }
+        }
+        // free voices are always preferred to held ones
+        let candidates = || {
+            let free = self.voices.iter().any(|v| v.key.is_none());
+            self.voices
+                .iter()
+                .enumerate()
+                .filter(move |(_, v)| !free || v.key.is_none())
+        };
+        let res = match self.stealing {
+            Stealing::Quietest => {
+                candidates().min_by_key(|(_, v)| v.sound.level().saturating_abs())
+            }
+            Stealing::Oldest | Stealing::SameNote => {
+                let age = self.age;
+                candidates().max_by_key(|(_, v)| age.wrapping_sub(v.age))
+            }
+        };
+        res.map_or(0, |(idx, _)| idx)
+    }
+}
+
+impl<S: Sound, const N: usize> Sound for Polyphony<S, N> {
+    fn vol(&self) -> i16 {
+        self.vol
+    }
+    fn rate(&self) -> u32 {
+        self.voices.first().map_or(RATE, |v| v.sound.rate())
+    }
+    fn level(&self) -> i16 {
+        self.voices
+            .iter()
+            .map(|v| v.sound.level())
+            .max()
+            .unwrap_or(0)
+    }
+    fn get(&self) -> i16 {
+        if N == 0 {
+            return 0;
+        }
+        // dividing by the number of voices gives enough headroom to
+        // never saturate
+        let sum: i32 = self.voices.iter().map(|v| v.sound.get() as i32).sum();
+        (sum / N as i32 * self.vol as i32 / MAX_VOL as i32) as i16
+    }
+    fn advance(&mut self) {
+        for voice in self.voices.iter_mut() {
+            voice.sound.advance();
+        }
+    }
+    fn set_freq(&mut self, freq: Freq) {
+        self.note_on(freq);
+    }
+    // bends the last started voice still held, which keeps its note
+    // for `note_off`
+    fn bend(&mut self, freq: Freq) {
+        let age = self.age;
+        let last = self
+            .voices
+            .iter_mut()
+            .filter(|v| v.key.is_some())
+            .min_by_key(|v| age.wrapping_sub(v.age));
+        if let Some(voice) = last {
+            voice.sound.bend(freq);
+        }
+    }
+    fn stop(&mut self) {
+        for voice in self.voices.iter_mut() {
+            voice.key = None;
+            voice.sound.stop();
+        }
+    }
+    fn set_vol(&mut self, vol: i16) {
+        self.vol = vol;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fm::Operator;
+    use crate::pitch::{A4, A5, C5};
+    use crate::Oscillator;
+
+    #[test]
+    fn bend_last_voice() {
+        let mut poly: Polyphony<Oscillator, 4> =
+            Polyphony::new(Oscillator::default(), Stealing::Oldest);
+        poly.note_on(A4);
+        poly.note_on(C5);
+        for _ in 0..10 {
+            poly.bend(A5);
+        }
+        let mut freqs: [Freq; 4] = core::array::from_fn(|i| poly.voices().nth(i).unwrap().freq());
+        freqs.sort();
+        assert_eq!(freqs, [Freq(0), Freq(0), A4, A5]);
+        poly.note_off(C5);
+        assert!(poly.voices.iter().all(|v| v.key != Some(C5)));
+    }
+
+    #[test]
+    fn bend_without_note() {
+        // an operator is bent even when stopped
+        let mut poly: Polyphony<Operator, 2> =
+            Polyphony::new(Operator::new(1, 1), Stealing::Oldest);
+        poly.bend(A5);
+        assert!(poly.voices().all(|v| v.freq() == Freq(0)));
+        poly.note_on(A4);
+        poly.note_on(C5);
+        poly.note_off(C5);
+        poly.bend(A5);
+        let mut freqs: [Freq; 2] = core::array::from_fn(|i| poly.voices().nth(i).unwrap().freq());
+        freqs.sort();
+        assert_eq!(freqs, [C5, A5]);
+    }
+}
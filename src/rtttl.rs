@@ -0,0 +1,361 @@
+use crate::pitch;
+use crate::songs::{ms_duration, split_note, Event};
+
+// RTTTL (Nokia ring tone) parser, for example
+// `mario:d=4,o=5,b=100:16e6,16e6,32p,8e6,16c6,8e6,8g6,8p,8g`.
+//
+// `Rtttl::new` is a `const fn` validating the whole string, so a
+// ring tone can be checked at compile time using the `rtttl!` macro.
+// The events are then parsed lazily while playing.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    MissingSection,
+    BadDefault,
+    BadDuration,
+    BadNote,
+    BadOctave,
+    BadTempo,
+    MissingComma,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Error {
+    // byte offset in the source string
+    pub pos: usize,
+    pub kind: ErrorKind,
+}
+const fn err<T>(pos: usize, kind: ErrorKind) -> Result<T, Error> {
+    Err(Error { pos, kind })
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Rtttl<'a> {
+    src: &'a str,
+    name_end: usize,
+    notes_start: usize,
+    duration: u8,
+    octave: u8,
+    bpm: u16,
+    // percentage of the duration of a note during which it sounds
+    pct: u8,
+}
+
+#[macro_export]
+macro_rules! rtttl {
+    ($src:expr) => {{
+        const RTTTL: $crate::rtttl::Rtttl<'static> = match $crate::rtttl::Rtttl::new($src) {
+            Ok(rtttl) => rtttl,
+            Err(_) => panic!("invalid RTTTL"),
+        };
+        RTTTL
+    }};
+}
+
+impl<'a> Rtttl<'a> {
+    pub const fn new(src: &'a str) -> Result<Self, Error> {
+        let bytes = src.as_bytes();
+        let mut pos = 0;
+        while pos < bytes.len() && bytes[pos] != b':' {
+            pos += 1;
+        }
+        if pos == bytes.len() {
+            return err(pos, ErrorKind::MissingSection);
+        }
+        let name_end = pos;
+        pos += 1;
+
+        let mut res = Rtttl {
+            src,
+            name_end,
+            notes_start: 0,
+            duration: 4,
+            octave: 6,
+            bpm: 63,
+            pct: 90,
+        };
+        loop {
+            pos = skip_spaces(bytes, pos);
+            if pos == bytes.len() {
+                return err(pos, ErrorKind::MissingSection);
+            }
+            if bytes[pos] == b':' {
+                break;
+            }
+            let key_pos = pos;
+            let key = bytes[pos];
+            pos = skip_spaces(bytes, pos + 1);
+            if pos == bytes.len() || bytes[pos] != b'=' {
+                return err(key_pos, ErrorKind::BadDefault);
+            }
+            pos = skip_spaces(bytes, pos + 1);
+            let (value, value_end) = parse_number(bytes, pos);
+            match key {
+                b'd' | b'D' if is_duration(value) => res.duration = value as u8,
+                b'd' | b'D' => return err(pos, ErrorKind::BadDuration),
+                b'o' | b'O' if is_octave(value) && value_end > pos => res.octave = value as u8,
+                b'o' | b'O' => return err(pos, ErrorKind::BadOctave),
+                b'b' | b'B' if value > 0 && value <= 900 => res.bpm = value as u16,
+                b'b' | b'B' => return err(pos, ErrorKind::BadTempo),
+                _ => return err(key_pos, ErrorKind::BadDefault),
+            }
+            pos = skip_spaces(bytes, value_end);
+            if pos < bytes.len() && bytes[pos] == b',' {
+                pos += 1;
+            } else if pos == bytes.len() || bytes[pos] != b':' {
+                return err(pos, ErrorKind::MissingComma);
+            }
+        }
+        res.notes_start = pos + 1;
+
+        pos = res.notes_start;
+        loop {
+            pos = skip_spaces(bytes, pos);
+            if pos == bytes.len() {
+                break;
+            }
+            pos = match parse_note(bytes, pos, res.duration, res.octave) {
+                Ok((_, end)) => skip_spaces(bytes, end),
+                Err(e) => return Err(e),
+            };
+            if pos < bytes.len() {
+                if bytes[pos] != b',' {
+                    return err(pos, ErrorKind::MissingComma);
+                }
+                pos += 1;
+            }
+        }
+        Ok(res)
+    }
+    pub fn name(&self) -> &'a str {
+        &self.src[..self.name_end]
+    }
+    // the defaults of the notes
+    pub const fn duration(&self) -> u8 {
+        self.duration
+    }
+    pub const fn octave(&self) -> u8 {
+        self.octave
+    }
+    pub const fn bpm(&self) -> u16 {
+        self.bpm
+    }
+    pub const fn pct(&self) -> u8 {
+        self.pct
+    }
+    pub const fn with_pct(mut self, pct: u8) -> Self {
+        self.pct = if pct > 100 { 100 } else { pct };
+        self
+    }
+    pub fn events(&self) -> Events<'a> {
+        Events {
+            bytes: self.src.as_bytes(),
+            pos: self.notes_start,
+            duration: self.duration,
+            octave: self.octave,
+            whole_us: 4 * 60 * 1_000_000 / self.bpm as u32,
+            pct: self.pct,
+            rest: None,
+        }
+    }
+    pub fn ms_duration(&self) -> u32 {
+        ms_duration(self.events())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Events<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    duration: u8,
+    octave: u8,
+    whole_us: u32,
+    pct: u8,
+    rest: Option<u32>,
+}
+impl<'a> Iterator for Events<'a> {
+    type Item = Event;
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(us) = self.rest.take() {
+            return Some(Event::Rest { us });
+        }
+        let pos = skip_spaces(self.bytes, self.pos);
+        let pos = if pos < self.bytes.len() && self.bytes[pos] == b',' {
+            skip_spaces(self.bytes, pos + 1)
+        } else {
+            pos
+        };
+        let (note, end) = parse_note(self.bytes, pos, self.duration, self.octave).ok()?;
+        self.pos = end;
+        match note {
+            Note {
+                key: Some(key),
+                n,
+                d,
+            } => {
+                let (event, rest) = split_note(self.whole_us, (pitch::midi(key), n, d, self.pct));
+                self.rest = rest;
+                Some(event)
+            }
+            Note { key: None, n, d } => Some(Event::Rest {
+                us: (self.whole_us as u64 * n as u64 / d as u64) as u32,
+            }),
+        }
+    }
+}
+
+struct Note {
+    // MIDI note number, `None` for a pause
+    key: Option<u8>,
+    n: u8,
+    d: u8,
+}
+
+const fn parse_note(
+    bytes: &[u8],
+    mut pos: usize,
+    duration: u8,
+    octave: u8,
+) -> Result<(Note, usize), Error> {
+    let (value, end) = parse_number(bytes, pos);
+    let duration = if end == pos {
+        duration
+    } else if is_duration(value) {
+        value as u8
+    } else {
+        return err(pos, ErrorKind::BadDuration);
+    };
+    pos = end;
+    if pos == bytes.len() {
+        return err(pos, ErrorKind::BadNote);
+    }
+    let semitone = match bytes[pos] {
+        b'c' | b'C' => 0,
+        b'd' | b'D' => 2,
+        b'e' | b'E' => 4,
+        b'f' | b'F' => 5,
+        b'g' | b'G' => 7,
+        b'a' | b'A' => 9,
+        b'b' | b'B' | b'h' | b'H' => 11,
+        b'p' | b'P' => 12,
+        _ => return err(pos, ErrorKind::BadNote),
+    };
+    pos += 1;
+    let sharp = pos < bytes.len() && bytes[pos] == b'#';
+    if sharp {
+        pos += 1;
+    }
+    let mut dotted = pos < bytes.len() && bytes[pos] == b'.';
+    if dotted {
+        pos += 1;
+    }
+    let (value, end) = parse_number(bytes, pos);
+    let octave = if end == pos {
+        octave
+    } else if is_octave(value) {
+        value as u8
+    } else {
+        return err(pos, ErrorKind::BadOctave);
+    };
+    pos = end;
+    if !dotted && pos < bytes.len() && bytes[pos] == b'.' {
+        dotted = true;
+        pos += 1;
+    }
+    let (n, d) = if dotted {
+        (3, duration * 2)
+    } else {
+        (1, duration)
+    };
+    let key = if semitone == 12 {
+        None
+    } else {
+        Some(12 * (octave + 1) + semitone + sharp as u8)
+    };
+    Ok((Note { key, n, d }, pos))
+}
+
+const fn is_duration(value: u32) -> bool {
+    matches!(value, 1 | 2 | 4 | 8 | 16 | 32 | 64)
+}
+const fn is_octave(value: u32) -> bool {
+    value <= 8
+}
+
+// Returns the parsed number and the position after it. No digits
+// gives 0 at the same position.
+const fn parse_number(bytes: &[u8], mut pos: usize) -> (u32, usize) {
+    let mut res = 0u32;
+    while pos < bytes.len() && bytes[pos].is_ascii_digit() && res < 10_000 {
+        res = res * 10 + (bytes[pos] - b'0') as u32;
+        pos += 1;
+    }
+    (res, pos)
+}
+
+const fn skip_spaces(bytes: &[u8], mut pos: usize) -> usize {
+    while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+        pos += 1;
+    }
+    pos
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notes_and_rests() {
+        let rtttl = rtttl!("tune:d=4,o=5,b=60:a,8p,c#6.,8e.");
+        assert_eq!(rtttl.name(), "tune");
+        let note = |key, us| Event::Note {
+            pitch: pitch::midi(key),
+            us,
+        };
+        let rest = |us| Event::Rest { us };
+        assert!(rtttl.events().eq([
+            note(81, 900_000),
+            rest(100_000),
+            rest(500_000),
+            note(85, 1_350_000),
+            rest(150_000),
+            note(76, 675_000),
+            rest(75_000),
+        ]));
+        assert_eq!(rtttl.ms_duration(), 3750);
+    }
+
+    #[test]
+    fn defaults() {
+        let rtttl = Rtttl::new("tune::c").unwrap();
+        assert_eq!((rtttl.duration(), rtttl.octave(), rtttl.bpm()), (4, 6, 63));
+        assert!(rtttl.events().eq([
+            Event::Note {
+                pitch: pitch::midi(84),
+                us: 857_142,
+            },
+            Event::Rest { us: 95_238 },
+        ]));
+        let legato = rtttl.with_pct(200);
+        assert_eq!(legato.pct(), 100);
+        assert!(legato.events().eq([Event::Note {
+            pitch: pitch::midi(84),
+            us: 952_380,
+        }]));
+    }
+
+    #[test]
+    fn errors() {
+        let error = |src| Rtttl::new(src).map(|_| ()).map_err(|e| (e.pos, e.kind));
+        assert_eq!(error("tune"), Err((4, ErrorKind::MissingSection)));
+        assert_eq!(error("t:d=4"), Err((5, ErrorKind::MissingComma)));
+        assert_eq!(error("t:x=1:a"), Err((2, ErrorKind::BadDefault)));
+        assert_eq!(error("t:d=3:a"), Err((4, ErrorKind::BadDuration)));
+        assert_eq!(error("t:o=9:a"), Err((4, ErrorKind::BadOctave)));
+        assert_eq!(error("t:b=0:a"), Err((4, ErrorKind::BadTempo)));
+        assert_eq!(error("t:d=4 o=5:a"), Err((6, ErrorKind::MissingComma)));
+        assert_eq!(error("t::a,x"), Err((5, ErrorKind::BadNote)));
+        assert_eq!(error("t::a b"), Err((5, ErrorKind::MissingComma)));
+        assert_eq!(error("t::a9"), Err((4, ErrorKind::BadOctave)));
+    }
+}
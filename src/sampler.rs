@@ -0,0 +1,210 @@
+use crate::{compute_ratio, Freq, Sound, MAX_VOL, RATE};
+
+// Playback of recorded samples, for example from flash. The data is
+// played at its own rate for its root pitch, and resampled with a
+// linear interpolation for the other frequencies. While the note is
+// held, the sustain loop is repeated; after `stop`, the playback goes
+// on after the loop, so the end of the data is the release tail.
+
+#[derive(Clone, Copy, Debug)]
+pub enum Data {
+    I16(&'static [i16]),
+    I8(&'static [i8]),
+}
+impl Data {
+    pub fn len(&self) -> usize {
+        match self {
+            Data::I16(data) => data.len(),
+            Data::I8(data) => data.len(),
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    fn get(&self, idx: usize) -> i16 {
+        match self {
+            Data::I16(data) => data.get(idx).copied().unwrap_or(0),
+            Data::I8(data) => data.get(idx).map_or(0, |&s| (s as i16) << 8),
+        }
+    }
+}
+
+// sustain loops, between a start and an end (excluded) sample
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Loop {
+    None,
+    Forward(usize, usize),
+    PingPong(usize, usize),
+}
+
+#[derive(Clone, Debug)]
+pub struct Sampler {
+    data: Data,
+    root: Freq,
+    data_rate: u32,
+    looping: Loop,
+    rate: u32,
+    vol: i16,
+    freq: Freq,
+    playing: bool,
+    released: bool,
+    backward: bool,
+    // position in the data and its increment, in Q16 samples
+    pos: i64,
+    inc: i64,
+}
+impl Sampler {
+    pub fn new(data: Data, root: Freq, data_rate: u32) -> Self {
+        Self::with_rate(data, root, data_rate, RATE)
+    }
+    // played at `rate`, the data being recorded at `data_rate`
+    pub fn with_rate(data: Data, root: Freq, data_rate: u32, rate: u32) -> Self {
+        Sampler {
+            data,
+            root,
+            data_rate,
+            looping: Loop::None,
+            rate,
+            vol: MAX_VOL,
+            freq: Freq(0),
+            playing: false,
+            released: false,
+            backward: false,
+            pos: 0,
+            inc: 0,
+        }
+    }
+    // An invalid loop is ignored, and a ping-pong loop of one sample,
+    // which has nowhere to bounce, repeats it as a forward one.
+    pub fn with_loop(mut self, looping: Loop) -> Self {
+        self.looping = match looping {
+            Loop::Forward(start, end) | Loop::PingPong(start, end)
+                if start >= end || end > self.data.len() =>
+            {
+                Loop::None
+            }
+            Loop::PingPong(start, end) if end == start + 1 => Loop::Forward(start, end),
+            looping => looping,
+        };
+        self
+    }
+    pub fn freq(&self) -> Freq {
+        self.freq
+    }
+    // the loop while the note is held
+    fn sustain(&self) -> Loop {
+        if self.released {
+            Loop::None
+        } else {
+            self.looping
+        }
+    }
+}
+
+impl Sound for Sampler {
+    fn vol(&self) -> i16 {
+        self.vol
+    }
+    fn rate(&self) -> u32 {
+        self.rate
+    }
+    fn get(&self) -> i16 {
+        if !self.playing {
+            return 0;
+        }
+        let idx = (self.pos >> 16) as usize;
+        let next = match self.sustain() {
+            Loop::Forward(start, end) if idx + 1 == end => start,
+            Loop::PingPong(_, end) if idx + 1 == end => idx,
+            _ => idx + 1,
+        };
+        let frac = (self.pos & 0xffff) as u32;
+        let res = compute_ratio(self.data.get(idx), self.data.get(next), frac, 1 << 16);
+        (res as i32 * self.vol as i32 / MAX_VOL as i32) as i16
+    }
+    fn advance(&mut self) {
+        if !self.playing {
+            return;
+        }
+        self.pos += if self.backward { -self.inc } else { self.inc };
+        match self.sustain() {
+            Loop::Forward(start, end) => {
+                let (start, end) = ((start as i64) << 16, (end as i64) << 16);
+                while self.pos >= end {
+                    self.pos -= end - start;
+                }
+            }
+            Loop::PingPong(start, end) => {
+                // bouncing on the last sample of the loop
+                let (start, end) = ((start as i64) << 16, ((end - 1) as i64) << 16);
+                if self.pos >= end && end > start {
+                    self.pos = (2 * end - self.pos).max(start);
+                    self.backward = true;
+                } else if self.pos <= start && self.backward {
+                    self.pos = (2 * start - self.pos).min(end);
+                    self.backward = false;
+                }
+            }
+            Loop::None => self.backward = false,
+        }
+        self.playing = self.pos >= 0 && self.pos < (self.data.len() as i64) << 16;
+    }
+    fn set_freq(&mut self, freq: Freq) {
+        self.pos = 0;
+        self.playing = true;
+        self.released = false;
+        self.backward = false;
+        self.bend(freq);
+    }
+    fn bend(&mut self, freq: Freq) {
+        self.freq = freq;
+        let num = (freq.mhz() as u128 * self.data_rate as u128) << 16;
+        let denom = self.root.mhz() as u128 * self.rate as u128;
+        self.inc = (num / denom.max(1)).min(i32::MAX as u128) as i64;
+    }
+    fn sync(&mut self) {
+        if self.playing {
+            self.pos = 0;
+            self.backward = false;
+        }
+    }
+    fn stop(&mut self) {
+        self.released = true;
+    }
+    fn set_vol(&mut self, vol: i16) {
+        self.vol = vol;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static DATA: [i16; 8] = [0, 1000, 2000, 3000, 4000, 5000, 6000, 7000];
+
+    fn play(looping: Loop, samples: &mut [i16]) {
+        let mut sampler =
+            Sampler::new(Data::I16(&DATA), Freq::from_hz(100), RATE).with_loop(looping);
+        sampler.set_freq(Freq::from_hz(100));
+        for s in samples.iter_mut() {
+            *s = sampler.step();
+        }
+    }
+
+    #[test]
+    fn ping_pong() {
+        let mut samples = [0; 12];
+        play(Loop::PingPong(2, 6), &mut samples);
+        assert_eq!(
+            samples,
+            [0, 1000, 2000, 3000, 4000, 5000, 4000, 3000, 2000, 3000, 4000, 5000]
+        );
+    }
+
+    #[test]
+    fn single_sample_loop() {
+        let mut samples = [0; 8];
+        play(Loop::PingPong(3, 4), &mut samples);
+        assert_eq!(samples, [0, 1000, 2000, 3000, 3000, 3000, 3000, 3000]);
+    }
+}
@@ -1,77 +1,336 @@
+use crate::drums::{CLAP, CLOSED_HAT, KICK, OPEN_HAT, SNARE};
 use crate::pitch::*;
-use crate::Action;
+use crate::{compute_ratio, Action, MAX_VOL};
+use Mark::*;
 
 #[derive(Clone, Debug)]
 pub struct Score {
     pub tempo: u8,
-    pub notes: &'static [(u16, u8, u8, u8)],
+    pub notes: &'static [Mark],
 }
 impl Score {
     pub fn events(&self) -> Events {
-        Events {
-            whole_ms: 60 * 1000 / (self.tempo as u32),
-            notes: self.notes.iter(),
-            rest: None,
-        }
+        Events::new(self.tempo, self.notes)
     }
     pub fn ms_events(&self) -> MsEvents {
         self.events().ms_events()
     }
     pub fn ms_duration(&self) -> u32 {
-        self.events().map(|e| e.ms_duration()).sum()
+        ms_duration(self.events())
     }
 }
+
+#[derive(Clone, Debug)]
+pub struct Track {
+    pub instrument: usize,
+    pub notes: &'static [Mark],
+}
+#[derive(Clone, Debug)]
+pub struct Song {
+    pub tempo: u8,
+    pub tracks: &'static [Track],
+}
+impl Song {
+    pub fn tracks(&self) -> impl Iterator<Item = (usize, Events)> {
+        let tempo = self.tempo;
+        self.tracks
+            .iter()
+            .map(move |t| (t.instrument, Events::new(tempo, t.notes)))
+    }
+    pub fn ms_duration(&self) -> u32 {
+        self.tracks()
+            .map(|(_, events)| ms_duration(events))
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+// A score is a list of marks. Repeats can't be nested, and when
+// coming back with `DaCapo` or `DalSegno`, the repeats are not taken
+// and only the last ending of each repeat is played.
+#[derive(Clone, Copy, Debug)]
+pub enum Mark {
+    // pitch, duration as n/d whole note, and percentage of the
+    // duration during which the note sounds
+    Note(Freq, u8, u8, u8),
+    // velocity of the following notes
+    Vel(i16),
+    // velocity of the next note only
+    Accent(i16),
+    // crescendo or decrescendo to a velocity over n/d whole notes
+    Ramp(i16, u8, u8),
+    // new tempo, in whole notes per minute
+    Tempo(u8),
+    // ritardando or accelerando to a tempo over n/d whole notes
+    Rit(u8, u8, u8),
+    RepeatStart,
+    // end of a repeated section, played the given number of times
+    RepeatEnd(u8),
+    // ending only played at the given pass of the repeated section,
+    // non last endings must finish with a `RepeatEnd`
+    Volta(u8),
+    Segno,
+    Coda,
+    // jump to `Coda` when coming back
+    ToCoda,
+    // stop here when coming back
+    Fine,
+    DaCapo,
+    DalSegno,
+}
+
+// durations of the ramps are counted in 1/3840 of whole note, so that
+// triplets and 1/256 notes are exact
+const WHOLE: u32 = 3840;
+
+#[derive(Clone, Copy, Debug)]
+struct Slide {
+    from: i16,
+    to: i16,
+    ticks: u32,
+    total: u32,
+}
+impl Slide {
+    fn new(from: i16, to: i16, n: u8, d: u8) -> Self {
+        use cast::u32;
+        Slide {
+            from,
+            to,
+            ticks: 0,
+            total: WHOLE * u32(n) / u32(d),
+        }
+    }
+    // the value at the current position, `None` if the ramp is
+    // finished; then moves forward for a note of n/d
+    fn advance(&mut self, n: u8, d: u8) -> Option<i16> {
+        use cast::u32;
+        if self.ticks >= self.total {
+            return None;
+        }
+        let res = compute_ratio(self.from, self.to, self.ticks, self.total);
+        self.ticks += WHOLE * u32(n) / u32(d);
+        Some(res)
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Events {
-    whole_ms: u32,
-    notes: ::core::slice::Iter<'static, (u16, u8, u8, u8)>,
+    tempo: u8,
+    marks: &'static [Mark],
+    idx: usize,
+    vel: i16,
+    accent: Option<i16>,
+    ramp: Option<Slide>,
+    rit: Option<Slide>,
+    last_vol: i16,
+    repeat_start: usize,
+    pass: u8,
+    segno: usize,
+    coming_back: bool,
+    note: Option<Event>,
     rest: Option<u32>,
 }
 impl Events {
+    fn new(tempo: u8, marks: &'static [Mark]) -> Self {
+        Events {
+            tempo,
+            marks,
+            idx: 0,
+            vel: MAX_VOL,
+            accent: None,
+            ramp: None,
+            rit: None,
+            last_vol: MAX_VOL,
+            repeat_start: 0,
+            pass: 1,
+            segno: 0,
+            coming_back: false,
+            note: None,
+            rest: None,
+        }
+    }
     pub fn ms_events(self) -> MsEvents {
         MsEvents {
             events: self,
             wait_ms: 0,
+            us: 0,
+        }
+    }
+    fn note_vol(&mut self, n: u8, d: u8) -> i16 {
+        if let Some(ramp) = self.ramp.as_mut() {
+            match ramp.advance(n, d) {
+                Some(vel) => self.vel = vel,
+                None => {
+                    self.vel = ramp.to;
+                    self.ramp = None;
+                }
+            }
+        }
+        self.accent.take().unwrap_or(self.vel)
+    }
+    fn note_whole_us(&mut self, n: u8, d: u8) -> u32 {
+        if let Some(rit) = self.rit.as_mut() {
+            match rit.advance(n, d) {
+                Some(tempo) => self.tempo = tempo as u8,
+                None => {
+                    self.tempo = rit.to as u8;
+                    self.rit = None;
+                }
+            }
+        }
+        60 * 1_000_000 / (self.tempo.max(1) as u32)
+    }
+    // skips an ending not played at this pass, up to the next ending
+    // or after the end of the repeated section
+    fn skip_ending(&mut self) {
+        while let Some(mark) = self.marks.get(self.idx) {
+            match mark {
+                Volta(_) => return,
+                RepeatEnd(_) => {
+                    self.idx += 1;
+                    return;
+                }
+                _ => self.idx += 1,
+            }
+        }
+    }
+    // is the ending starting at `idx` the last one of its section
+    fn is_last_ending(&self) -> bool {
+        self.marks[self.idx..]
+            .iter()
+            .take_while(|m| !matches!(m, Volta(_)))
+            .all(|m| !matches!(m, RepeatEnd(_)))
+    }
+    fn jump_to(&mut self, target: fn(&Mark) -> bool) {
+        self.idx = match self.marks[self.idx..].iter().position(target) {
+            Some(pos) => self.idx + pos,
+            None => self.marks.len(),
         }
     }
 }
 impl ::core::iter::Iterator for Events {
     type Item = Event;
     fn next(&mut self) -> Option<Self::Item> {
-        use cast::u32;
-        match self.rest {
-            Some(ms) => {
-                self.rest = None;
-                Some(Event::Rest { ms })
-            }
-            None => self.notes.next().map(|&(pitch, n, d, pct)| {
-                let ms = self.whole_ms * u32(n) / u32(d);
-                let note_ms = ms * u32(pct) / 100;
-                let rest_ms = ms * (100 - u32(pct)) / 100;
-                if rest_ms > 0 {
-                    self.rest = Some(rest_ms);
+        if let Some(note) = self.note.take() {
+            return Some(note);
+        }
+        if let Some(us) = self.rest.take() {
+            return Some(Event::Rest { us });
+        }
+        loop {
+            let mark = *self.marks.get(self.idx)?;
+            self.idx += 1;
+            match mark {
+                Note(pitch, n, d, pct) => {
+                    let vol = self.note_vol(n, d);
+                    let whole_us = self.note_whole_us(n, d);
+                    let (event, rest) = split_note(whole_us, (pitch, n, d, pct));
+                    self.rest = rest;
+                    if vol == self.last_vol {
+                        return Some(event);
+                    }
+                    self.last_vol = vol;
+                    self.note = Some(event);
+                    return Some(Event::Vol { vol });
+                }
+                Vel(vel) => {
+                    self.vel = vel;
+                    self.ramp = None;
+                }
+                Accent(vel) => self.accent = Some(vel),
+                Ramp(to, n, d) => self.ramp = Some(Slide::new(self.vel, to, n, d)),
+                Tempo(tempo) => {
+                    self.tempo = tempo;
+                    self.rit = None;
+                }
+                Rit(to, n, d) => self.rit = Some(Slide::new(self.tempo as i16, to as i16, n, d)),
+                RepeatStart => {
+                    self.repeat_start = self.idx;
+                    self.pass = 1;
+                }
+                RepeatEnd(count) if !self.coming_back && self.pass < count => {
+                    self.pass += 1;
+                    self.idx = self.repeat_start;
                 }
-                Event::Note { pitch, ms: note_ms }
-            }),
+                // a following bare end goes back to here
+                RepeatEnd(_) => {
+                    self.pass = 1;
+                    self.repeat_start = self.idx;
+                }
+                Volta(_) if self.coming_back && !self.is_last_ending() => self.skip_ending(),
+                Volta(n) if !self.coming_back && n != self.pass => self.skip_ending(),
+                Volta(_) => {}
+                Segno => self.segno = self.idx,
+                Coda => {}
+                ToCoda if self.coming_back => self.jump_to(|m| matches!(m, Coda)),
+                ToCoda => {}
+                Fine if self.coming_back => self.idx = self.marks.len(),
+                Fine => {}
+                DaCapo | DalSegno if self.coming_back => {}
+                DaCapo => {
+                    self.coming_back = true;
+                    self.idx = 0;
+                }
+                DalSegno => {
+                    self.coming_back = true;
+                    self.idx = self.segno;
+                }
+            }
         }
     }
 }
-#[derive(Clone, Copy, Debug)]
+
+// The note event of a `(pitch, n, d, pct)` note, and the duration of
+// the rest that follows it, if any.
+pub(crate) fn split_note(
+    whole_us: u32,
+    (pitch, n, d, pct): (Freq, u8, u8, u8),
+) -> (Event, Option<u32>) {
+    use cast::u64;
+    let us = u64(whole_us) * u64(n) / u64(d);
+    let note_us = us * u64(pct.min(100)) / 100;
+    let rest = if us > note_us {
+        Some((us - note_us) as u32)
+    } else {
+        None
+    };
+    (
+        Event::Note {
+            pitch,
+            us: note_us as u32,
+        },
+        rest,
+    )
+}
+
+// The duration of some events in ms, without accumulating rounding
+// errors.
+pub(crate) fn ms_duration<I: Iterator<Item = Event>>(events: I) -> u32 {
+    let us: u64 = events.map(|e| e.us_duration() as u64).sum();
+    (us / 1000) as u32
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Event {
-    Note { pitch: u16, ms: u32 },
-    Rest { ms: u32 },
+    Note { pitch: Freq, us: u32 },
+    Rest { us: u32 },
+    Vol { vol: i16 },
 }
 impl Event {
-    pub fn ms_duration(&self) -> u32 {
+    pub fn us_duration(&self) -> u32 {
         match *self {
-            Event::Note { ms, .. } => ms,
-            Event::Rest { ms, .. } => ms,
+            Event::Note { us, .. } => us,
+            Event::Rest { us, .. } => us,
+            Event::Vol { .. } => 0,
         }
     }
+    pub fn ms_duration(&self) -> u32 {
+        self.us_duration() / 1000
+    }
     pub fn to_action(&self) -> crate::Action {
         match *self {
             Event::Note { pitch, .. } => Action::Start(pitch),
             Event::Rest { .. } => Action::Stop,
+            Event::Vol { vol } => Action::Vol(vol),
         }
     }
 }
@@ -79,6 +338,8 @@ impl Event {
 pub struct MsEvents {
     events: Events,
     wait_ms: u32,
+    // elapsed time at the end of the last event
+    us: u64,
 }
 impl ::core::iter::Iterator for MsEvents {
     type Item = MsEvent;
@@ -87,21 +348,21 @@ impl ::core::iter::Iterator for MsEvents {
             self.wait_ms -= 1;
             return Some(MsEvent::Wait);
         }
-        self.events.next().map(|e| match e {
-            Event::Note { pitch, ms } => {
-                self.wait_ms = ms;
-                MsEvent::BeginNote { pitch }
-            }
-            Event::Rest { ms } => {
-                self.wait_ms = ms;
-                MsEvent::EndNote
-            }
+        let e = self.events.next()?;
+        let start_ms = self.us / 1000;
+        self.us += e.us_duration() as u64;
+        self.wait_ms = (self.us / 1000 - start_ms) as u32;
+        Some(match e {
+            Event::Note { pitch, .. } => MsEvent::BeginNote { pitch },
+            Event::Rest { .. } => MsEvent::EndNote,
+            Event::Vol { vol } => MsEvent::Vol { vol },
         })
     }
 }
 pub enum MsEvent {
-    BeginNote { pitch: u16 },
+    BeginNote { pitch: Freq },
     EndNote,
+    Vol { vol: i16 },
     Wait,
 }
 
@@ -109,303 +370,462 @@ pub static AU_FEU_LES_POMPIERS: Score = Score {
     tempo: 120 / 4,
     notes: &AU_FEU_LES_POMPIERS_NOTES,
 };
-static AU_FEU_LES_POMPIERS_NOTES: [(u16, u8, u8, u8); 48] = [
-    (G5, 1, 4, 90),
-    (G5, 1, 4, 90),
-    (B5, 1, 8, 90),
-    (G5, 1, 8, 90),
-    (D5, 1, 4, 90),
-    (D5, 1, 8, 90),
-    (D5, 1, 16, 90),
-    (D5, 1, 16, 90),
-    (D5, 1, 8, 90),
-    (D5, 1, 8, 90),
-    (B5, 1, 4, 90),
-    (G5, 1, 4, 90),
-    (G5, 1, 4, 90),
-    (G5, 1, 4, 90),
-    (B5, 1, 8, 90),
-    (G5, 1, 8, 90),
-    (D5, 1, 4, 90),
-    (D5, 1, 8, 90),
-    (D5, 1, 16, 90),
-    (D5, 1, 16, 90),
-    (D5, 1, 8, 90),
-    (D5, 1, 8, 90),
-    (G5, 1, 2, 90),
-    (G5, 1, 8, 90),
-    (G5, 1, 8, 90),
-    (G5, 1, 8, 90),
-    (B5, 1, 8, 90),
-    (D6, 1, 8, 90),
-    (B5, 1, 8, 90),
-    (G5, 1, 4, 90),
-    (D5, 1, 8, 90),
-    (D6, 1, 8, 90),
-    (D5, 1, 8, 90),
-    (D6, 1, 8, 90),
-    (B5, 1, 4, 90),
-    (G5, 1, 4, 90),
-    (G5, 1, 8, 90),
-    (G5, 1, 8, 90),
-    (G5, 1, 8, 90),
-    (B5, 1, 8, 90),
-    (D6, 1, 8, 90),
-    (B5, 1, 8, 90),
-    (G5, 1, 4, 90),
-    (D5, 1, 8, 90),
-    (D6, 1, 8, 90),
-    (D5, 1, 8, 90),
-    (D6, 1, 8, 90),
-    (G5, 1, 2, 90),
+static AU_FEU_LES_POMPIERS_NOTES: [Mark; 48] = [
+    Note(G5, 1, 4, 90),
+    Note(G5, 1, 4, 90),
+    Note(B5, 1, 8, 90),
+    Note(G5, 1, 8, 90),
+    Note(D5, 1, 4, 90),
+    Note(D5, 1, 8, 90),
+    Note(D5, 1, 16, 90),
+    Note(D5, 1, 16, 90),
+    Note(D5, 1, 8, 90),
+    Note(D5, 1, 8, 90),
+    Note(B5, 1, 4, 90),
+    Note(G5, 1, 4, 90),
+    Note(G5, 1, 4, 90),
+    Note(G5, 1, 4, 90),
+    Note(B5, 1, 8, 90),
+    Note(G5, 1, 8, 90),
+    Note(D5, 1, 4, 90),
+    Note(D5, 1, 8, 90),
+    Note(D5, 1, 16, 90),
+    Note(D5, 1, 16, 90),
+    Note(D5, 1, 8, 90),
+    Note(D5, 1, 8, 90),
+    Note(G5, 1, 2, 90),
+    Note(G5, 1, 8, 90),
+    Note(G5, 1, 8, 90),
+    Note(G5, 1, 8, 90),
+    Note(B5, 1, 8, 90),
+    Note(D6, 1, 8, 90),
+    Note(B5, 1, 8, 90),
+    Note(G5, 1, 4, 90),
+    Note(D5, 1, 8, 90),
+    Note(D6, 1, 8, 90),
+    Note(D5, 1, 8, 90),
+    Note(D6, 1, 8, 90),
+    Note(B5, 1, 4, 90),
+    Note(G5, 1, 4, 90),
+    Note(G5, 1, 8, 90),
+    Note(G5, 1, 8, 90),
+    Note(G5, 1, 8, 90),
+    Note(B5, 1, 8, 90),
+    Note(D6, 1, 8, 90),
+    Note(B5, 1, 8, 90),
+    Note(G5, 1, 4, 90),
+    Note(D5, 1, 8, 90),
+    Note(D6, 1, 8, 90),
+    Note(D5, 1, 8, 90),
+    Note(D6, 1, 8, 90),
+    Note(G5, 1, 2, 90),
+];
+
+// a percussion track, for `Kit`
+pub static BACK_BEAT: Score = Score {
+    tempo: 100 / 4,
+    notes: &BACK_BEAT_NOTES,
+};
+static BACK_BEAT_NOTES: [Mark; 11] = [
+    RepeatStart,
+    Note(KICK, 1, 8, 100),
+    Note(CLOSED_HAT, 1, 8, 100),
+    Note(SNARE, 1, 8, 100),
+    Note(CLOSED_HAT, 1, 8, 100),
+    Note(KICK, 1, 8, 100),
+    Note(KICK, 1, 8, 100),
+    Note(SNARE, 1, 8, 100),
+    Note(OPEN_HAT, 1, 8, 100),
+    RepeatEnd(2),
+    Note(CLAP, 1, 4, 100),
 ];
 
 pub static BATEAU_SUR_LEAU: Score = Score {
     tempo: 80 / 4,
     notes: &BATEAU_SUR_LEAU_NOTES,
 };
-static BATEAU_SUR_LEAU_NOTES: [(u16, u8, u8, u8); 23] = [
-    (E5, 1, 4, 95),
-    (C5, 1, 4, 95),
-    (E5, 1, 4, 95),
-    (C5, 1, 4, 95),
-    (D5, 1, 8, 95),
-    (E5, 1, 8, 95),
-    (F5, 1, 8, 95),
-    (E5, 1, 8, 95),
-    (D5, 1, 8, 95),
-    (G5, 1, 8, 95),
-    (E5, 1, 8, 95),
-    (C5, 1, 8, 95),
-    (E5, 1, 4, 95),
-    (C5, 1, 4, 95),
-    (E5, 1, 4, 95),
-    (C5, 1, 4, 95),
-    (D5, 1, 8, 95),
-    (E5, 1, 8, 95),
-    (F5, 1, 8, 95),
-    (E5, 1, 8, 95),
-    (D5, 1, 8, 95),
-    (G5, 1, 8, 95),
-    (C5, 1, 4, 95),
+static BATEAU_SUR_LEAU_NOTES: [Mark; 23] = [
+    Note(E5, 1, 4, 95),
+    Note(C5, 1, 4, 95),
+    Note(E5, 1, 4, 95),
+    Note(C5, 1, 4, 95),
+    Note(D5, 1, 8, 95),
+    Note(E5, 1, 8, 95),
+    Note(F5, 1, 8, 95),
+    Note(E5, 1, 8, 95),
+    Note(D5, 1, 8, 95),
+    Note(G5, 1, 8, 95),
+    Note(E5, 1, 8, 95),
+    Note(C5, 1, 8, 95),
+    Note(E5, 1, 4, 95),
+    Note(C5, 1, 4, 95),
+    Note(E5, 1, 4, 95),
+    Note(C5, 1, 4, 95),
+    Note(D5, 1, 8, 95),
+    Note(E5, 1, 8, 95),
+    Note(F5, 1, 8, 95),
+    Note(E5, 1, 8, 95),
+    Note(D5, 1, 8, 95),
+    Note(G5, 1, 8, 95),
+    Note(C5, 1, 4, 95),
 ];
 
 pub static FRERE_JACQUES: Score = Score {
     tempo: 140 / 4,
     notes: &FRERE_JACQUES_NOTES,
 };
-static FRERE_JACQUES_NOTES: [(u16, u8, u8, u8); 32] = [
-    (C5, 1, 4, 90),
-    (D5, 1, 4, 90),
-    (E5, 1, 4, 90),
-    (C5, 1, 4, 90),
-    (C5, 1, 4, 90),
-    (D5, 1, 4, 90),
-    (E5, 1, 4, 90),
-    (C5, 1, 4, 90),
-    (E5, 1, 4, 90),
-    (F5, 1, 4, 90),
-    (G5, 1, 2, 90),
-    (E5, 1, 4, 90),
-    (F5, 1, 4, 90),
-    (G5, 1, 2, 90),
-    (G5, 3, 16, 90),
-    (A5, 1, 16, 90),
-    (G5, 1, 8, 90),
-    (F5, 1, 8, 90),
-    (E5, 1, 4, 90),
-    (C5, 1, 4, 90),
-    (G5, 3, 16, 90),
-    (A5, 1, 16, 90),
-    (G5, 1, 8, 90),
-    (F5, 1, 8, 90),
-    (E5, 1, 4, 90),
-    (C5, 1, 4, 90),
-    (C5, 1, 4, 90),
-    (G4, 1, 4, 90),
-    (C5, 1, 2, 90),
-    (C5, 1, 4, 90),
-    (G4, 1, 4, 90),
-    (C5, 1, 2, 90),
+static FRERE_JACQUES_NOTES: [Mark; 32] = [
+    Note(C5, 1, 4, 90),
+    Note(D5, 1, 4, 90),
+    Note(E5, 1, 4, 90),
+    Note(C5, 1, 4, 90),
+    Note(C5, 1, 4, 90),
+    Note(D5, 1, 4, 90),
+    Note(E5, 1, 4, 90),
+    Note(C5, 1, 4, 90),
+    Note(E5, 1, 4, 90),
+    Note(F5, 1, 4, 90),
+    Note(G5, 1, 2, 90),
+    Note(E5, 1, 4, 90),
+    Note(F5, 1, 4, 90),
+    Note(G5, 1, 2, 90),
+    Note(G5, 3, 16, 90),
+    Note(A5, 1, 16, 90),
+    Note(G5, 1, 8, 90),
+    Note(F5, 1, 8, 90),
+    Note(E5, 1, 4, 90),
+    Note(C5, 1, 4, 90),
+    Note(G5, 3, 16, 90),
+    Note(A5, 1, 16, 90),
+    Note(G5, 1, 8, 90),
+    Note(F5, 1, 8, 90),
+    Note(E5, 1, 4, 90),
+    Note(C5, 1, 4, 90),
+    Note(C5, 1, 4, 90),
+    Note(G4, 1, 4, 90),
+    Note(C5, 1, 2, 90),
+    Note(C5, 1, 4, 90),
+    Note(G4, 1, 4, 90),
+    Note(C5, 1, 2, 90),
 ];
 
 pub static IL_ETAIT_UN_PETIT_NAVIRE: Score = Score {
     tempo: 100 / 4,
     notes: &IL_ETAIT_UN_PETIT_NAVIRE_NOTES,
 };
-static IL_ETAIT_UN_PETIT_NAVIRE_NOTES: [(u16, u8, u8, u8); 74] = [
-    (B5, 1, 8, 90),
-    (B5, 1, 8, 90),
-    (B5, 1, 8, 90),
-    (D5, 1, 4, 90),
-    (B5, 1, 4, 90),
-    (C6, 1, 8, 90),
-    (B5, 1, 8, 90),
-    (B5, 1, 4, 90),
-    (A5, 1, 8, 90),
-    (A5, 1, 8, 90),
-    (A5, 1, 8, 90),
-    (A5, 1, 8, 90),
-    (D5, 1, 4, 90),
-    (A5, 1, 4, 90),
-    (B5, 1, 8, 90),
-    (A5, 1, 8, 90),
-    (A5, 1, 4, 90),
-    (G5, 1, 8, 90),
-    (B5, 1, 8, 90),
-    (B5, 1, 8, 90),
-    (B5, 1, 8, 90),
-    (B5, 1, 4, 90),
-    (B5, 1, 4, 90),
-    (B5, 1, 8, 90),
-    (D6, 1, 8, 90),
-    (C6, 1, 8, 90),
-    (B5, 1, 8, 90),
-    (A5, 1, 8, 90),
-    (A5, 1, 8, 90),
-    (A5, 1, 8, 90),
-    (A5, 1, 8, 90),
-    (A5, 1, 4, 90),
-    (A5, 1, 4, 90),
-    (A5, 1, 8, 90),
-    (C6, 1, 8, 90),
-    (B5, 1, 8, 90),
-    (A5, 1, 8, 90),
-    (G5, 1, 8, 90),
-    (D5, 1, 8, 90),
-    (G5, 1, 8, 90),
-    (B5, 1, 8, 90),
-    (D6, 1, 2, 90),
-    (B5, 1, 4, 90),
-    (D6, 1, 4, 90),
-    (B5, 1, 4, 90),
-    (D6, 1, 4, 90),
-    (C6, 3, 16, 90),
-    (B5, 1, 16, 90),
-    (A5, 1, 2, 90),
-    (A5, 3, 16, 90),
-    (B5, 1, 16, 90),
-    (C6, 3, 16, 90),
-    (D6, 1, 16, 90),
-    (E6, 1, 4, 90),
-    (D6, 1, 4, 90),
-    (E6, 1, 4, 90),
-    (D6, 1, 4, 90),
-    (B5, 3, 4, 90),
-    (B5, 1, 4, 90),
-    (D6, 1, 4, 90),
-    (B5, 1, 4, 90),
-    (D6, 1, 4, 90),
-    (C6, 3, 16, 90),
-    (B5, 1, 16, 90),
-    (A5, 1, 2, 90),
-    (A5, 3, 16, 90),
-    (B5, 1, 16, 90),
-    (C6, 3, 16, 90),
-    (D6, 1, 16, 90),
-    (E6, 1, 4, 90),
-    (D6, 1, 4, 90),
-    (E6, 1, 4, 90),
-    (D6, 1, 4, 90),
-    (G5, 5, 8, 90),
+static IL_ETAIT_UN_PETIT_NAVIRE_NOTES: [Mark; 74] = [
+    Note(B5, 1, 8, 90),
+    Note(B5, 1, 8, 90),
+    Note(B5, 1, 8, 90),
+    Note(D5, 1, 4, 90),
+    Note(B5, 1, 4, 90),
+    Note(C6, 1, 8, 90),
+    Note(B5, 1, 8, 90),
+    Note(B5, 1, 4, 90),
+    Note(A5, 1, 8, 90),
+    Note(A5, 1, 8, 90),
+    Note(A5, 1, 8, 90),
+    Note(A5, 1, 8, 90),
+    Note(D5, 1, 4, 90),
+    Note(A5, 1, 4, 90),
+    Note(B5, 1, 8, 90),
+    Note(A5, 1, 8, 90),
+    Note(A5, 1, 4, 90),
+    Note(G5, 1, 8, 90),
+    Note(B5, 1, 8, 90),
+    Note(B5, 1, 8, 90),
+    Note(B5, 1, 8, 90),
+    Note(B5, 1, 4, 90),
+    Note(B5, 1, 4, 90),
+    Note(B5, 1, 8, 90),
+    Note(D6, 1, 8, 90),
+    Note(C6, 1, 8, 90),
+    Note(B5, 1, 8, 90),
+    Note(A5, 1, 8, 90),
+    Note(A5, 1, 8, 90),
+    Note(A5, 1, 8, 90),
+    Note(A5, 1, 8, 90),
+    Note(A5, 1, 4, 90),
+    Note(A5, 1, 4, 90),
+    Note(A5, 1, 8, 90),
+    Note(C6, 1, 8, 90),
+    Note(B5, 1, 8, 90),
+    Note(A5, 1, 8, 90),
+    Note(G5, 1, 8, 90),
+    Note(D5, 1, 8, 90),
+    Note(G5, 1, 8, 90),
+    Note(B5, 1, 8, 90),
+    Note(D6, 1, 2, 90),
+    Note(B5, 1, 4, 90),
+    Note(D6, 1, 4, 90),
+    Note(B5, 1, 4, 90),
+    Note(D6, 1, 4, 90),
+    Note(C6, 3, 16, 90),
+    Note(B5, 1, 16, 90),
+    Note(A5, 1, 2, 90),
+    Note(A5, 3, 16, 90),
+    Note(B5, 1, 16, 90),
+    Note(C6, 3, 16, 90),
+    Note(D6, 1, 16, 90),
+    Note(E6, 1, 4, 90),
+    Note(D6, 1, 4, 90),
+    Note(E6, 1, 4, 90),
+    Note(D6, 1, 4, 90),
+    Note(B5, 3, 4, 90),
+    Note(B5, 1, 4, 90),
+    Note(D6, 1, 4, 90),
+    Note(B5, 1, 4, 90),
+    Note(D6, 1, 4, 90),
+    Note(C6, 3, 16, 90),
+    Note(B5, 1, 16, 90),
+    Note(A5, 1, 2, 90),
+    Note(A5, 3, 16, 90),
+    Note(B5, 1, 16, 90),
+    Note(C6, 3, 16, 90),
+    Note(D6, 1, 16, 90),
+    Note(E6, 1, 4, 90),
+    Note(D6, 1, 4, 90),
+    Note(E6, 1, 4, 90),
+    Note(D6, 1, 4, 90),
+    Note(G5, 5, 8, 90),
 ];
 
 pub static LAVENTURIER: Score = Score {
     tempo: 160 / 4,
     notes: &LAVENTURIER_NOTES,
 };
-static LAVENTURIER_NOTES: [(u16, u8, u8, u8); 17] = [
-    (A4, 1, 4, 95),
-    (D5, 1, 8, 95),
-    (E5, 1, 8, 95),
-    (G5, 1, 4, 95),
-    (E5, 1, 4, 95),
-    (D5, 1, 8, 95),
-    (C5, 1, 4, 95),
-    (A4, 5, 8, 95),
-    (C5, 1, 4, 95),
-    (D5, 1, 8, 95),
-    (E5, 1, 8, 95),
-    (G5, 1, 4, 95),
-    (E5, 1, 4, 95),
-    (D5, 1, 8, 95),
-    (E5, 1, 8, 95),
-    (D5, 1, 8, 95),
-    (E5, 5, 8, 95),
+static LAVENTURIER_NOTES: [Mark; 17] = [
+    Note(A4, 1, 4, 95),
+    Note(D5, 1, 8, 95),
+    Note(E5, 1, 8, 95),
+    Note(G5, 1, 4, 95),
+    Note(E5, 1, 4, 95),
+    Note(D5, 1, 8, 95),
+    Note(C5, 1, 4, 95),
+    Note(A4, 5, 8, 95),
+    Note(C5, 1, 4, 95),
+    Note(D5, 1, 8, 95),
+    Note(E5, 1, 8, 95),
+    Note(G5, 1, 4, 95),
+    Note(E5, 1, 4, 95),
+    Note(D5, 1, 8, 95),
+    Note(E5, 1, 8, 95),
+    Note(D5, 1, 8, 95),
+    Note(E5, 5, 8, 95),
 ];
 
 pub static MARIO_THEME_INTRO: Score = Score {
     tempo: 185 / 4,
     notes: &MARIO_THEME_INTRO_NOTES,
 };
-static MARIO_THEME_INTRO_NOTES: [(u16, u8, u8, u8); 7] = [
-    (E5, 1, 8, 50),
-    (E5, 1, 4, 25),
-    (E5, 1, 4, 25),
-    (C5, 1, 8, 50),
-    (E5, 1, 4, 25),
-    (G5, 1, 2, 25),
-    (G4, 1, 2, 25),
+static MARIO_THEME_INTRO_NOTES: [Mark; 7] = [
+    Note(E5, 1, 8, 50),
+    Note(E5, 1, 4, 25),
+    Note(E5, 1, 4, 25),
+    Note(C5, 1, 8, 50),
+    Note(E5, 1, 4, 25),
+    Note(G5, 1, 2, 25),
+    Note(G4, 1, 2, 25),
 ];
 
 pub static SO_WHAT: Score = Score {
     tempo: 120 / 8 * 3,
     notes: &SO_WHAT_NOTES,
 };
-static SO_WHAT_NOTES: [(u16, u8, u8, u8); 42] = [
-    (D3, 2, 8, 0),
-    (D3, 1, 8, 80),
-    (A3, 2, 8, 80),
-    (B3, 1, 8, 80),
-    (C4, 2, 8, 80),
-    (D4, 1, 8, 80),
-    (E4, 2, 8, 80),
-    (C4, 1, 8, 30),
-    (D4, 6, 8, 60),
-    (E5, 5, 8, 100),
-    (D5, 1, 8, 50),
-    (D3, 2, 8, 0),
-    (D3, 1, 8, 80),
-    (A3, 2, 8, 80),
-    (B3, 1, 8, 80),
-    (C4, 2, 8, 80),
-    (D4, 1, 8, 80),
-    (E4, 2, 8, 80),
-    (C4, 1, 8, 30),
-    (D4, 2, 8, 80),
-    (A3, 4, 8, 60),
-    (E5, 5, 8, 100),
-    (D5, 1, 8, 50),
-    (D3, 2, 8, 0),
-    (D3, 1, 8, 80),
-    (A3, 2, 8, 80),
-    (B3, 1, 8, 80),
-    (C4, 2, 8, 80),
-    (D4, 1, 8, 80),
-    (E4, 2, 8, 80),
-    (C4, 1, 8, 30),
-    (D4, 6, 8, 60),
-    (E5, 5, 8, 100),
-    (D5, 1, 8, 50),
-    (E4, 2, 8, 00),
-    (E4, 4, 8, 80),
-    (E4, 3, 8, 80),
-    (E4, 3, 8, 80),
-    (D4, 5, 8, 80),
-    (A3, 1, 8, 100),
-    (E5, 5, 8, 100),
-    (D5, 1, 8, 50),
+static SO_WHAT_NOTES: [Mark; 37] = [
+    Vel(MAX_VOL / 3 * 2),
+    RepeatStart,
+    Note(D3, 2, 8, 0),
+    Note(D3, 1, 8, 80),
+    Note(A3, 2, 8, 80),
+    Note(B3, 1, 8, 80),
+    Note(C4, 2, 8, 80),
+    Note(D4, 1, 8, 80),
+    Note(E4, 2, 8, 80),
+    Note(C4, 1, 8, 30),
+    Volta(1),
+    Note(D4, 6, 8, 60),
+    Accent(MAX_VOL),
+    Note(E5, 5, 8, 100),
+    Note(D5, 1, 8, 50),
+    RepeatEnd(3),
+    Volta(2),
+    Note(D4, 2, 8, 80),
+    Note(A3, 4, 8, 60),
+    Accent(MAX_VOL),
+    Note(E5, 5, 8, 100),
+    Note(D5, 1, 8, 50),
+    RepeatEnd(3),
+    Volta(3),
+    Note(D4, 6, 8, 60),
+    Accent(MAX_VOL),
+    Note(E5, 5, 8, 100),
+    Note(D5, 1, 8, 50),
+    Note(E4, 2, 8, 00),
+    Note(E4, 4, 8, 80),
+    Note(E4, 3, 8, 80),
+    Note(E4, 3, 8, 80),
+    Note(D4, 5, 8, 80),
+    Note(A3, 1, 8, 100),
+    Accent(MAX_VOL),
+    Note(E5, 5, 8, 100),
+    Note(D5, 1, 8, 50),
 ];
 
 pub static THIRD_KIND: Score = Score {
     tempo: 120 / 4,
     notes: &THIRD_KIND_NOTES,
 };
-static THIRD_KIND_NOTES: [(u16, u8, u8, u8); 6] = [
-    (BF5, 1, 4, 100),
-    (C6, 1, 4, 100),
-    (AF5, 1, 4, 100),
-    (AF4, 1, 4, 100),
-    (EF5, 1, 2, 100),
-    (BF5, 1, 2, 0),
+static THIRD_KIND_NOTES: [Mark; 6] = [
+    Note(BF5, 1, 4, 100),
+    Note(C6, 1, 4, 100),
+    Note(AF5, 1, 4, 100),
+    Note(AF4, 1, 4, 100),
+    Note(EF5, 1, 2, 100),
+    Note(BF5, 1, 2, 0),
 ];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pitches(marks: &'static [Mark]) -> impl Iterator<Item = Freq> {
+        Score {
+            tempo: 60,
+            notes: marks,
+        }
+        .events()
+        .filter_map(|e| match e {
+            Event::Note { pitch, .. } => Some(pitch),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn repeats_in_a_row() {
+        static MARKS: [Mark; 6] = [
+            RepeatStart,
+            Note(A4, 1, 4, 100),
+            RepeatEnd(2),
+            Note(B4, 1, 4, 100),
+            RepeatEnd(2),
+            Note(C5, 1, 4, 100),
+        ];
+        assert_eq!(
+            Score {
+                tempo: 60,
+                notes: &MARKS
+            }
+            .events()
+            .count(),
+            5
+        );
+        assert!(pitches(&MARKS).eq([A4, A4, B4, B4, C5]));
+    }
+
+    #[test]
+    fn voltas() {
+        static MARKS: [Mark; 7] = [
+            RepeatStart,
+            Note(A4, 1, 4, 100),
+            Volta(1),
+            Note(B4, 1, 4, 100),
+            RepeatEnd(2),
+            Volta(2),
+            Note(C5, 1, 4, 100),
+        ];
+        assert!(pitches(&MARKS).eq([A4, B4, A4, C5]));
+    }
+
+    #[test]
+    fn da_capo_al_fine() {
+        static MARKS: [Mark; 7] = [
+            RepeatStart,
+            Note(A4, 1, 4, 100),
+            RepeatEnd(2),
+            Note(B4, 1, 4, 100),
+            Fine,
+            Note(C5, 1, 4, 100),
+            DaCapo,
+        ];
+        // the repeats are not played when coming back
+        assert!(pitches(&MARKS).eq([A4, A4, B4, C5, A4, B4]));
+    }
+
+    #[test]
+    fn dal_segno_al_coda() {
+        static MARKS: [Mark; 8] = [
+            Note(A4, 1, 4, 100),
+            Segno,
+            Note(B4, 1, 4, 100),
+            ToCoda,
+            Note(C5, 1, 4, 100),
+            DalSegno,
+            Coda,
+            Note(D5, 1, 4, 100),
+        ];
+        assert!(pitches(&MARKS).eq([A4, B4, C5, B4, D5]));
+    }
+
+    #[test]
+    fn tempo_and_rests() {
+        static MARKS: [Mark; 3] = [Note(A4, 1, 4, 50), Tempo(120), Note(B4, 1, 4, 100)];
+        let events = Score {
+            tempo: 60,
+            notes: &MARKS,
+        }
+        .events();
+        assert!(events.eq([
+            Event::Note {
+                pitch: A4,
+                us: 125_000
+            },
+            Event::Rest { us: 125_000 },
+            Event::Note {
+                pitch: B4,
+                us: 125_000
+            },
+        ]));
+    }
+
+    #[test]
+    fn velocities() {
+        static MARKS: [Mark; 4] = [
+            Vel(MAX_VOL / 2),
+            Note(A4, 1, 4, 100),
+            Accent(MAX_VOL),
+            Note(A4, 1, 4, 100),
+        ];
+        let vols = Score {
+            tempo: 60,
+            notes: &MARKS,
+        }
+        .events()
+        .filter_map(|e| match e {
+            Event::Vol { vol } => Some(vol),
+            _ => None,
+        });
+        assert!(vols.eq([MAX_VOL / 2, MAX_VOL]));
+    }
+
+    #[test]
+    fn ramp() {
+        static MARKS: [Mark; 5] = [
+            Vel(1000),
+            Ramp(3000, 1, 2),
+            Note(A4, 1, 4, 100),
+            Note(A4, 1, 4, 100),
+            Note(A4, 1, 4, 100),
+        ];
+        let vols = Score {
+            tempo: 60,
+            notes: &MARKS,
+        }
+        .events()
+        .filter_map(|e| match e {
+            Event::Vol { vol } => Some(vol),
+            _ => None,
+        });
+        assert!(vols.eq([1000, 2000, 3000]));
+    }
+}
@@ -0,0 +1,158 @@
+use crate::oscillator::TablePhase;
+use crate::{compute_ratio, Freq, Sound, MAX_VOL, RATE};
+
+// An oscillator playing from a bank of single periods, as the PPG and
+// Waldorf wavetables. The position in the bank, in 256ths of a frame,
+// crossfades between two adjacent frames; sweeping it, for example
+// with `modulate` as a `Target::Param`, gives an evolving timbre.
+
+pub const FRAME_STEPS: u32 = 256;
+
+#[derive(Clone, Debug)]
+pub struct Wavetable {
+    pub frames: &'static [[i16; 256]],
+    vol: i16,
+    freq: Freq,
+    position: u32,
+    offset: i32,
+    table: TablePhase,
+}
+impl Wavetable {
+    pub fn new(frames: &'static [[i16; 256]]) -> Self {
+        Self::with_rate(frames, RATE)
+    }
+    pub fn with_rate(frames: &'static [[i16; 256]], rate: u32) -> Self {
+        Wavetable {
+            frames,
+            vol: MAX_VOL,
+            freq: Freq(0),
+            position: 0,
+            offset: 0,
+            table: TablePhase::new(rate),
+        }
+    }
+    pub fn freq(&self) -> Freq {
+        self.freq
+    }
+    pub fn position(&self) -> u32 {
+        self.position
+    }
+    pub fn set_position(&mut self, position: u32) {
+        self.position = position;
+    }
+    // moves the position by `offset` 256ths of a frame
+    pub fn modulate(&mut self, offset: i16) {
+        self.offset = offset as i32;
+    }
+    fn max_position(&self) -> i64 {
+        self.frames.len().saturating_sub(1) as i64 * FRAME_STEPS as i64
+    }
+}
+
+impl Sound for Wavetable {
+    fn vol(&self) -> i16 {
+        self.vol
+    }
+    fn rate(&self) -> u32 {
+        self.table.rate
+    }
+    fn get(&self) -> i16 {
+        if self.frames.is_empty() {
+            return 0;
+        }
+        let position = (self.position as i64 + self.offset as i64).clamp(0, self.max_position());
+        let frame = (position / FRAME_STEPS as i64) as usize;
+        let frac = (position % FRAME_STEPS as i64) as u32;
+        let res = if frac == 0 {
+            self.table.read(&self.frames[frame])
+        } else {
+            let before = self.table.read(&self.frames[frame]);
+            let after = self.table.read(&self.frames[frame + 1]);
+            compute_ratio(before, after, frac, FRAME_STEPS)
+        };
+        (res as i32 * self.vol as i32 / MAX_VOL as i32) as i16
+    }
+    fn advance(&mut self) {
+        self.table.advance();
+    }
+    fn set_freq(&mut self, freq: Freq) {
+        self.freq = freq;
+        self.table.set_freq(freq);
+    }
+    fn bend(&mut self, freq: Freq) {
+        if self.table.is_running() {
+            self.set_freq(freq);
+        }
+    }
+    fn sync(&mut self) {
+        self.table.sync();
+    }
+    fn set_phase(&mut self, phase: u32) {
+        self.table.set_phase(phase);
+    }
+    fn stop(&mut self) {
+        self.table.stop();
+    }
+    fn set_vol(&mut self, vol: i16) {
+        self.vol = vol;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crossfade() {
+        static FRAMES: [[i16; 256]; 2] = [[1000; 256], [3000; 256]];
+        let mut table = Wavetable::new(&FRAMES);
+        table.set_freq(Freq::from_hz(440));
+        table.set_position(FRAME_STEPS / 4);
+        assert_eq!(table.get(), 1500);
+        table.modulate(FRAME_STEPS as i16 * 2);
+        assert_eq!(table.get(), 3000);
+    }
+
+    #[test]
+    fn morph_endpoints() {
+        use crate::oscillator::SIN;
+        use crate::Oscillator;
+        static NEG_SIN: [i16; 256] = {
+            let mut res = [0; 256];
+            let mut i = 0;
+            while i < 256 {
+                res[i] = -SIN[i];
+                i += 1;
+            }
+            res
+        };
+        static FRAMES: [[i16; 256]; 2] = [SIN, NEG_SIN];
+        let freq = Freq::from_hz(440);
+        let mut osc = Oscillator::default();
+        osc.set_freq(freq);
+        let mut table = Wavetable::new(&FRAMES);
+        table.set_freq(freq);
+        for i in 0..1000 {
+            let expected = osc.step() as i32;
+            // the first and last frames, beyond them, and the middle
+            let morphs = [
+                (0, 0, expected),
+                (0, -500, expected),
+                (256, 0, -expected),
+                (0, 1000, -expected),
+                (128, 0, 0),
+            ];
+            for &(position, offset, expected) in &morphs {
+                table.set_position(position);
+                table.modulate(offset);
+                assert!(
+                    (table.get() as i32 - expected).abs() <= 1,
+                    "{} {}",
+                    i,
+                    position
+                );
+            }
+            table.advance();
+        }
+    }
+}